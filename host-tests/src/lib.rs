@@ -0,0 +1,13 @@
+//! Hardware independent modules of `embassy-canopen`, included by path to run their unit tests on the host.
+
+// Crate internals are only used by the modules not included here
+#![allow(dead_code)]
+
+#[path = "../../src/dcf.rs"]
+pub mod dcf;
+#[path = "../../src/object_dictionary.rs"]
+pub mod object_dictionary;
+#[path = "../../src/pdo.rs"]
+pub mod pdo;
+#[path = "../../src/sdo.rs"]
+pub mod sdo;
@@ -0,0 +1,136 @@
+/// Largest concise DCF the NMT manager downloads, it is copied out of its domain for the download.
+pub const MAX_CONCISE_DCF_SIZE: usize = 512;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DcfError {
+    /// The data ends within the entry count or an entry.
+    Truncated,
+    /// There are bytes left after the last entry.
+    TrailingData,
+    /// The concise DCF does not fit into [`MAX_CONCISE_DCF_SIZE`].
+    TooLarge,
+}
+
+/// Object value of a concise DCF, written to the slave in the order of the DCF.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DcfEntry<'d> {
+    pub index: u16,
+    pub subindex: u8,
+    pub data: &'d [u8],
+}
+
+/// Concise DCF as defined by CiA 302-3: the number of entries (u32), followed by the entries,
+/// each made of index (u16), sub-index (u8), data size (u32) and data, all little endian.
+#[derive(Copy, Clone, Debug)]
+pub struct ConciseDcf<'d> {
+    count: u32,
+    entries: &'d [u8],
+}
+
+impl<'d> ConciseDcf<'d> {
+    /// Checks the structure of a concise DCF, so iterating its entries cannot fail.
+    pub fn parse(data: &'d [u8]) -> Result<Self, DcfError> {
+        let (count, entries) = split_u32(data).ok_or(DcfError::Truncated)?;
+
+        let mut rest = entries;
+        for _ in 0..count {
+            let (_, tail) = next_entry(rest).ok_or(DcfError::Truncated)?;
+            rest = tail;
+        }
+        if !rest.is_empty() {
+            return Err(DcfError::TrailingData);
+        }
+
+        Ok(Self { count, entries })
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.count as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = DcfEntry<'d>> {
+        let mut rest = self.entries;
+        (0..self.count).map_while(move |_| {
+            let (entry, tail) = next_entry(rest)?;
+            rest = tail;
+            Some(entry)
+        })
+    }
+}
+
+fn split_u32(data: &[u8]) -> Option<(u32, &[u8])> {
+    let (value, rest) = data.split_first_chunk::<4>()?;
+    Some((u32::from_le_bytes(*value), rest))
+}
+
+fn next_entry(data: &[u8]) -> Option<(DcfEntry<'_>, &[u8])> {
+    let (index, rest) = data.split_first_chunk::<2>()?;
+    let (&subindex, rest) = rest.split_first()?;
+    let (size, rest) = split_u32(rest)?;
+    let (data, rest) = rest.split_at_checked(size as usize)?;
+
+    let entry = DcfEntry {
+        index: u16::from_le_bytes(*index),
+        subindex,
+        data,
+    };
+    Some((entry, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x1017:0 = 1000 ms, 0x1800:1 = 0x40000181
+    const DCF: [u8; 24] = [
+        2, 0, 0, 0, //
+        0x17, 0x10, 0x00, 2, 0, 0, 0, 0xE8, 0x03, //
+        0x00, 0x18, 0x01, 4, 0, 0, 0, 0x81, 0x01, 0x00, 0x40,
+    ];
+
+    #[test]
+    fn parses_entries_in_order() {
+        let dcf = ConciseDcf::parse(&DCF).unwrap();
+        assert_eq!(dcf.len(), 2);
+
+        let mut entries = dcf.entries();
+        assert_eq!(entries.next(), Some(DcfEntry { index: 0x1017, subindex: 0, data: &[0xE8, 0x03] }));
+        assert_eq!(entries.next(), Some(DcfEntry { index: 0x1800, subindex: 1, data: &[0x81, 0x01, 0x00, 0x40] }));
+        assert_eq!(entries.next(), None);
+    }
+
+    #[test]
+    fn parses_empty_dcf() {
+        let dcf = ConciseDcf::parse(&[0, 0, 0, 0]).unwrap();
+        assert!(dcf.is_empty());
+        assert_eq!(dcf.entries().next(), None);
+    }
+
+    #[test]
+    fn rejects_truncated_dcf() {
+        // Within the entry count, an entry header and the data of the last entry
+        for len in [0, 3, 4, 8, 13, 23] {
+            assert_eq!(ConciseDcf::parse(&DCF[..len]).unwrap_err(), DcfError::Truncated, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn rejects_trailing_data() {
+        let mut data = [0; 25];
+        data[..24].copy_from_slice(&DCF);
+        assert_eq!(ConciseDcf::parse(&data).unwrap_err(), DcfError::TrailingData);
+    }
+
+    #[test]
+    fn rejects_count_beyond_data() {
+        let mut data = DCF;
+        data[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(ConciseDcf::parse(&data).unwrap_err(), DcfError::Truncated);
+    }
+}
@@ -0,0 +1,164 @@
+use defmt::warn;
+use embassy_futures::select::{select, Either};
+use embassy_stm32::can::Frame;
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::{Receiver, Sender}, mutex::Mutex, signal::Signal};
+use embassy_time::{Duration, Instant, Timer};
+use heapless::Vec;
+
+use crate::{
+    nmt::{NmtService, NmtState},
+    object_dictionary::{ObjectDictionary, Value},
+};
+
+/// Number of EMCY requests buffered for the producer.
+pub const EMCY_QUEUE_SIZE: usize = 8;
+
+/// Number of distinct errors that can be active at the same time.
+pub const MAX_ACTIVE_ERRORS: usize = 8;
+
+/// Number of received EMCY messages buffered for the application.
+pub const EMCY_CONSUMER_QUEUE_SIZE: usize = 8;
+
+/// EMCY message of another node, received through the consumer entries in 0x1028.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EmcyMessage {
+    /// Sub-index of the consumer entry in 0x1028, which is the node ID of the producer.
+    pub node_id: u8,
+    pub error_code: u16,
+    pub error_register: u8,
+    pub manufacturer_data: [u8; 5],
+}
+
+impl EmcyMessage {
+    /// Decodes the 8 data bytes of an EMCY message.
+    pub fn new(node_id: u8, data: &[u8; 8]) -> Self {
+        Self {
+            node_id,
+            error_code: u16::from_le_bytes([data[0], data[1]]),
+            error_register: data[2],
+            manufacturer_data: [data[3], data[4], data[5], data[6], data[7]],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum EmcyRequest {
+    Raise { error_code: u16, data: [u8; 5] },
+    Clear(u16),
+}
+
+/// Raises and clears errors of the node, each change is reported by the [`EmcyProducer`].
+#[derive(Copy, Clone)]
+pub struct EmcyHandle<'b> {
+    pub(crate) requests: Sender<'b, ThreadModeRawMutex, EmcyRequest, EMCY_QUEUE_SIZE>,
+}
+
+impl EmcyHandle<'_> {
+    /// Raises the error with the CiA 301 `error_code`, `data` is sent as manufacturer specific error code.
+    ///
+    /// The first two bytes of `data` are also recorded in the error history (0x1003).
+    pub async fn raise(&self, error_code: u16, data: [u8; 5]) {
+        self.requests.send(EmcyRequest::Raise { error_code, data }).await;
+    }
+
+    /// Clears the error with the CiA 301 `error_code`, an error reset EMCY is sent if it was active.
+    pub async fn clear(&self, error_code: u16) {
+        self.requests.send(EmcyRequest::Clear(error_code)).await;
+    }
+}
+
+/// Sends EMCY messages on the COB-ID in 0x1014 and keeps the error register (0x1001)
+/// and the error history (0x1003) up to date.
+pub struct EmcyProducer<'a, 'b, const N: usize, const R: usize> {
+    pub(crate) object_dictionary: &'a Mutex<ThreadModeRawMutex, ObjectDictionary<N>>,
+    pub(crate) can_tx_sender: Sender<'b, ThreadModeRawMutex, Frame, R>,
+    pub(crate) requests: Receiver<'b, ThreadModeRawMutex, EmcyRequest, EMCY_QUEUE_SIZE>,
+    pub(crate) nmt_state_changed: &'b Signal<ThreadModeRawMutex, NmtState>,
+}
+
+impl<'a, 'b, const N: usize, const R: usize> EmcyProducer<'a, 'b, N, R> {
+    pub async fn run(&self) -> ! {
+        let mut active_errors: Vec<u16, MAX_ACTIVE_ERRORS> = Vec::new();
+        let mut inhibited_until = Instant::MIN;
+        let mut nmt_state = NmtState::Initializing;
+
+        loop {
+            let request = match select(self.requests.receive(), self.nmt_state_changed.wait()).await {
+                Either::First(request) => request,
+                // NMT resets restore 0x1001 to its default, so it is re-derived from the errors still active
+                Either::Second(state) => {
+                    nmt_state = state;
+                    self.set_error_register(error_register(&active_errors)).await;
+                    continue;
+                }
+            };
+
+            let (error_code, data) = match request {
+                EmcyRequest::Raise { error_code, data } => {
+                    if !active_errors.contains(&error_code) && active_errors.push(error_code).is_err() {
+                        warn!("EmcyProducer: too many active errors, {} not tracked", error_code);
+                    }
+                    // The first two manufacturer specific bytes are kept as additional information
+                    let additional_information = u16::from_le_bytes([data[0], data[1]]) as u32;
+                    self.object_dictionary.lock().await.push_error_history((additional_information << 16) | error_code as u32);
+                    (error_code, data)
+                }
+                EmcyRequest::Clear(error_code) => match active_errors.iter().position(|&e| e == error_code) {
+                    Some(position) => {
+                        active_errors.swap_remove(position);
+                        // Error reset
+                        (0x0000, [0; 5])
+                    }
+                    None => continue,
+                },
+            };
+
+            let error_register = error_register(&active_errors);
+            self.set_error_register(error_register).await;
+            let (cob_id, inhibit_time) = {
+                let locked_od = self.object_dictionary.lock().await;
+                let cob_id = locked_od.get_value(0x1014, 0).ok().and_then(|value| value.as_u32()).unwrap_or(0x8000_0000);
+                let inhibit_time = locked_od.get_value(0x1015, 0).ok().and_then(|value| value.as_u32()).unwrap_or(0);
+                (cob_id, inhibit_time)
+            };
+
+            // Bit 31 set: EMCY not valid, errors are only recorded while EMCY is not available
+            if cob_id & 0x8000_0000 != 0 || !nmt_state.allows(NmtService::Emcy) {
+                continue;
+            }
+
+            // Consecutive EMCY messages are at least the inhibit time (in 100 us) apart
+            Timer::at(inhibited_until).await;
+
+            let code = error_code.to_le_bytes();
+            let payload = [code[0], code[1], error_register, data[0], data[1], data[2], data[3], data[4]];
+            let msg = Frame::new_standard((cob_id & 0x7FF) as u16, &payload).unwrap();
+            self.can_tx_sender.send(msg).await;
+
+            inhibited_until = Instant::now() + Duration::from_micros(100 * inhibit_time as u64);
+        }
+    }
+
+    async fn set_error_register(&self, error_register: u8) {
+        if let Ok(entry) = self.object_dictionary.lock().await.get_entry_mut(0x1001, 0) {
+            entry.value = Value::Uint8(error_register);
+        }
+    }
+}
+
+// Error register (0x1001) bits of the active errors, bit 0 (generic error) is set for any error
+fn error_register(active_errors: &[u16]) -> u8 {
+    active_errors.iter().fold(0, |register, &error_code| {
+        let class = match error_code {
+            0x2000..=0x2FFF => 0x02, // Current
+            0x3000..=0x3FFF => 0x04, // Voltage
+            0x4000..=0x4FFF => 0x08, // Temperature
+            0x8000..=0x8FFF => 0x10, // Communication
+            0xFF00..=0xFFFF => 0x80, // Manufacturer specific
+            _ => 0x00,
+        };
+        register | class | 0x01
+    })
+}
@@ -0,0 +1,24 @@
+use crate::nmt::NmtState;
+
+/// Number of events buffered for the application.
+pub const EVENT_QUEUE_SIZE: usize = 8;
+
+/// Events the node reports to the application.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NodeEvent {
+    /// RPDO `n` (0-based) was not received within its event timer (0x1400 + n, sub-index 5).
+    RpdoTimeout(u16),
+    /// No heartbeat of a node monitored in 0x1016 was received within its consumer heartbeat time.
+    HeartbeatLost(u8),
+    /// The heartbeat of a node reported lost is received again.
+    HeartbeatRecovered(u8),
+    /// A node monitored in 0x1016 reported a new NMT state in its heartbeat.
+    RemoteStateChanged { node_id: u8, state: NmtState },
+    /// The NMT master did not guard the node within its life time (0x100C * 0x100D).
+    LifeGuardingFailed,
+    /// Node guarding RTRs are received again after the life guarding failed.
+    LifeGuardingResumed,
+    /// An NMT reset application restored the application objects (0x2000 and above) to their defaults.
+    ApplicationReset,
+}
@@ -1,8 +1,8 @@
 use defmt::warn;
-use embassy_futures::join;
+use embassy_futures::{join, select::{select, Either}};
 use embassy_stm32::can::Frame;
-use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::Sender, mutex::Mutex};
-use embassy_time::{Duration, Timer};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::Sender, mutex::Mutex, signal::Signal};
+use embassy_time::{Duration, Instant, Ticker, Timer};
 
 use crate::{nmt::NmtState, node::Context, object_dictionary::ObjectDictionary};
 
@@ -13,24 +13,68 @@ pub enum ErrorKind {
     NoEntry
 }
 
+/// Number of consumer heartbeat entries (0x1016), starting with the first, monitored by the node.
+pub(crate) const TRACKED_HEARTBEATS: usize = 32;
+
+/// Monitoring state of a consumer heartbeat entry kept by the node.
+#[derive(Copy, Clone)]
+pub(crate) struct HeartbeatConsumerState {
+    pub(crate) node_id: u8,
+    /// NMT state reported by the last heartbeat.
+    pub(crate) state: NmtState,
+    /// Time the consumer heartbeat time elapses, monitoring starts with the first heartbeat.
+    pub(crate) deadline: Option<Instant>,
+    pub(crate) lost: bool,
+}
+
+impl Default for HeartbeatConsumerState {
+    fn default() -> Self {
+        Self {
+            node_id: 0,
+            state: NmtState::Unknown,
+            deadline: None,
+            lost: false,
+        }
+    }
+}
+
+/// Node guarding state of the node, answering the RTRs of the NMT master.
+#[derive(Copy, Clone, Default)]
+pub(crate) struct LifeGuardingState {
+    /// Toggle bit of the next response.
+    pub(crate) toggle: bool,
+    /// Time the life time (0x100C * 0x100D) elapses, monitoring starts with the first RTR.
+    pub(crate) deadline: Option<Instant>,
+    pub(crate) failed: bool,
+}
+
+/// Splits a consumer heartbeat entry into node ID (bits 16-23) and heartbeat time in ms (bits 0-15).
+pub(crate) fn consumer_entry(value: u32) -> (u8, u16) {
+    ((value >> 16) as u8, value as u16)
+}
+
 pub struct HeartbeatProducer<'a, 'b, 'c, const N: usize, const R: usize> {
     pub(crate) context: &'c Mutex<ThreadModeRawMutex, Context>,
     pub(crate) object_dictionary: &'a Mutex<ThreadModeRawMutex, ObjectDictionary<N>>,
     pub(crate) can_tx_sender: Sender<'b, ThreadModeRawMutex, embassy_stm32::can::Frame, R>,
+    pub(crate) nmt_state_changed: &'b Signal<ThreadModeRawMutex, NmtState>,
 }
 
 impl<'a, 'b, 'c, const N: usize, const R: usize> HeartbeatProducer<'a, 'b, 'c, N, R> {
     pub async fn timeout(&self) -> Result<u16, ErrorKind> {
         let locked = self.object_dictionary.lock().await;
         match locked.get_entry(0x1017, 00) {
-            Some(value) => match value.value {
+            Ok(value) => match value.value {
                 crate::object_dictionary::Value::Uint16(t) => return Ok(t),
                 _ => Err(ErrorKind::ErrorType)
             },
-            None => Err(ErrorKind::NoEntry),
+            Err(_) => Err(ErrorKind::NoEntry),
         }
     }
 
+    /// Runs the producer. While the heartbeat is disabled 0x1017 is re-read every `on_error_timeout`.
+    ///
+    /// A heartbeat is sent right away on every NMT state change, which also restarts the period.
     pub async fn run(&self, on_error_timeout: Duration) -> ! {
         loop {
             let timeout = match self.timeout().await {
@@ -38,23 +82,34 @@ impl<'a, 'b, 'c, const N: usize, const R: usize> HeartbeatProducer<'a, 'b, 'c, N
                 Err(e) => {warn!("HeartbeatProducer: {}", e); 0},
             };
     
+            // 0 = heartbeat disabled, the node is guarded by the NMT master instead
             if timeout == 0 {
-                Timer::after(on_error_timeout).await;
+                select(Timer::after(on_error_timeout), self.nmt_state_changed.wait()).await;
                 continue;
             }
 
-            let node_id;
-            let nmt_state;
-            {
-                let locked_context = self.context.lock().await;
-                node_id = locked_context.node_id;
-                nmt_state = locked_context.nmt_state;
-            }
+            let mut ticker = Ticker::every(Duration::from_millis(timeout as u64));
+            loop {
+                let node_id;
+                let nmt_state;
+                {
+                    let locked_context = self.context.lock().await;
+                    node_id = locked_context.node_id;
+                    nmt_state = locked_context.nmt_state;
+                }
 
-            let msg = Frame::new_standard(0x700 + node_id as u16, &[nmt_state.into()]).unwrap();
+                // No heartbeat before the boot-up message
+                if nmt_state != NmtState::Initializing {
+                    let msg = Frame::new_standard(0x700 + node_id as u16, &[nmt_state.into()]).unwrap();
+                    self.can_tx_sender.send(msg).await;
+                }
 
-            self.can_tx_sender.send(msg).await;
-            Timer::after_millis(timeout as u64).await;
+                match select(ticker.next(), self.nmt_state_changed.wait()).await {
+                    // Restart with the new producer time when 0x1017 changed
+                    Either::First(_) if self.timeout().await.ok() == Some(timeout) => (),
+                    _ => break,
+                }
+            }
         }
     }
 }
\ No newline at end of file
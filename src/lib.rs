@@ -1,6 +1,16 @@
 #![no_std]
 
 mod nmt;
+mod nmt_master;
+mod nmt_manager;
 mod heartbeat;
+mod sdo;
+mod sdo_client;
+mod pdo;
+mod tpdo;
+mod event;
+mod sync;
+mod emcy;
+pub mod dcf;
 pub mod object_dictionary;
 pub mod node;
\ No newline at end of file
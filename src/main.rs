@@ -2,7 +2,7 @@
 #![no_main]
 
 use defmt::*;
-use embassy_canopen::node::{Context, HeartbeatProducer, Node, NodeReceiver, NodeSender};
+use embassy_canopen::node::{Context, EmcyProducer, HeartbeatProducer, Node, NodeChannels, NodeReceiver, NodeSender, SyncProducer, TpdoProducer};
 use embassy_canopen::object_dictionary::ObjectDictionary;
 use embassy_executor::Spawner;
 use embassy_stm32::can::filter::Mask32;
@@ -29,10 +29,11 @@ bind_interrupts!(struct Irqs {
 });
 
 
-static OBJECT_DICTIONARY: StaticCell<Mutex<ThreadModeRawMutex, ObjectDictionary<32>>> = StaticCell::new();
+static OBJECT_DICTIONARY: StaticCell<Mutex<ThreadModeRawMutex, ObjectDictionary<256>>> = StaticCell::new();
 static CAN_RX_CHANNEL: Channel<ThreadModeRawMutex, embassy_stm32::can::frame::Envelope, 10> = Channel::new();
 static CAN_TX_CHANNEL: Channel<ThreadModeRawMutex, embassy_stm32::can::Frame, 10> = Channel::new();
 static CONTEXT: StaticCell<Mutex<ThreadModeRawMutex, Context>> = StaticCell::new();
+static NODE_CHANNELS: NodeChannels = NodeChannels::new();
 
 #[embassy_executor::task]
 async fn node_receiver_task(mut receiver: NodeReceiver<'static, 10>) -> ! {
@@ -45,10 +46,25 @@ async fn node_sender_task(mut sender: NodeSender<'static, 10>) -> ! {
 }
 
 #[embassy_executor::task]
-async fn node_heartbeat_producer_task(producer: HeartbeatProducer<'static, 'static, 'static, 32, 10>) -> ! {
+async fn node_heartbeat_producer_task(producer: HeartbeatProducer<'static, 'static, 'static, 256, 10>) -> ! {
     producer.run(Duration::from_secs(5)).await
 }
 
+#[embassy_executor::task]
+async fn node_tpdo_producer_task(producer: TpdoProducer<'static, 'static, 'static, 256, 10, 4>) -> ! {
+    producer.run(Duration::from_millis(100)).await
+}
+
+#[embassy_executor::task]
+async fn node_sync_producer_task(producer: SyncProducer<'static, 'static, 'static, 256, 10>) -> ! {
+    producer.run(Duration::from_secs(1)).await
+}
+
+#[embassy_executor::task]
+async fn node_emcy_producer_task(producer: EmcyProducer<'static, 'static, 256, 10>) -> ! {
+    producer.run().await
+}
+
 #[embassy_executor::main]
 async fn main(spawner: Spawner) -> ! {
     let p = embassy_stm32::init(Default::default());
@@ -74,10 +90,13 @@ async fn main(spawner: Spawner) -> ! {
     
     let od = OBJECT_DICTIONARY.init(Mutex::new(ObjectDictionary::new_canopen_301(Default::default())));
     let ctx = CONTEXT.init(Mutex::new(Context::new(7)));
-    let (mut node, node_receiver, node_sender, heartbeat_producer) = Node::new(ctx, od, can_tx, can_rx, &CAN_RX_CHANNEL, &CAN_TX_CHANNEL);
+    let (mut node, node_receiver, node_sender, heartbeat_producer) = Node::new(ctx, od, can_tx, can_rx, &CAN_RX_CHANNEL, &CAN_TX_CHANNEL, &NODE_CHANNELS);
 
     spawner.spawn(node_receiver_task(node_receiver).unwrap());
     spawner.spawn(node_sender_task(node_sender).unwrap());
     spawner.spawn(node_heartbeat_producer_task(heartbeat_producer).unwrap());
+    spawner.spawn(node_tpdo_producer_task(node.tpdo_producer()).unwrap());
+    spawner.spawn(node_sync_producer_task(node.sync_producer()).unwrap());
+    spawner.spawn(node_emcy_producer_task(node.emcy_producer()).unwrap());
     node.process().await
 }
\ No newline at end of file
@@ -1,3 +1,4 @@
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, signal::Signal};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -69,4 +70,71 @@ impl Into<u8> for NmtCommand {
             NmtCommand::ResetCommunication => 130
         }
     }
-}
\ No newline at end of file
+}
+/// Communication services whose availability depends on the NMT state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NmtService {
+    Nmt,
+    /// Heartbeat and node guarding.
+    ErrorControl,
+    Sdo,
+    Sync,
+    Emcy,
+    Pdo,
+}
+
+impl NmtState {
+    /// State entered on `command` as defined by CiA 301, `None` if the command is not accepted in this state.
+    ///
+    /// Both resets lead to `Initializing`, from where the node enters `PreOperational` after the boot-up message.
+    pub fn transition(self, command: NmtCommand) -> Option<NmtState> {
+        if !matches!(self, NmtState::PreOperational | NmtState::Operational | NmtState::Stopped) {
+            return None;
+        }
+
+        match command {
+            NmtCommand::EnterOperational => Some(NmtState::Operational),
+            NmtCommand::EnterStopped => Some(NmtState::Stopped),
+            NmtCommand::EnterPreOperational => Some(NmtState::PreOperational),
+            NmtCommand::ResetDevice | NmtCommand::ResetCommunication => Some(NmtState::Initializing),
+            NmtCommand::Unknown => None,
+        }
+    }
+
+    /// Whether `service` is available in this state.
+    pub fn allows(self, service: NmtService) -> bool {
+        match self {
+            NmtState::Operational => true,
+            NmtState::PreOperational => service != NmtService::Pdo,
+            NmtState::Stopped => matches!(service, NmtService::Nmt | NmtService::ErrorControl),
+            NmtState::Initializing | NmtState::Unknown => false,
+        }
+    }
+}
+
+/// Notifies the producers of NMT state changes, each producer waits on its own signal.
+pub(crate) struct NmtStateSignals {
+    pub(crate) heartbeat: Signal<ThreadModeRawMutex, NmtState>,
+    pub(crate) sync: Signal<ThreadModeRawMutex, NmtState>,
+    pub(crate) tpdo: Signal<ThreadModeRawMutex, NmtState>,
+    pub(crate) emcy: Signal<ThreadModeRawMutex, NmtState>,
+}
+
+impl NmtStateSignals {
+    pub(crate) const fn new() -> Self {
+        Self {
+            heartbeat: Signal::new(),
+            sync: Signal::new(),
+            tpdo: Signal::new(),
+            emcy: Signal::new(),
+        }
+    }
+
+    pub(crate) fn signal(&self, state: NmtState) {
+        self.heartbeat.signal(state);
+        self.sync.signal(state);
+        self.tpdo.signal(state);
+        self.emcy.signal(state);
+    }
+}
@@ -0,0 +1,193 @@
+use defmt::{info, warn};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex, signal::Signal};
+
+use crate::{
+    dcf::{ConciseDcf, DcfError, MAX_CONCISE_DCF_SIZE},
+    nmt::NmtCommand,
+    nmt_master::NmtMaster,
+    object_dictionary::{ObjectDictionary, Value},
+    sdo_client::{SdoClient, SdoClientError},
+};
+
+/// Reason a slave was not booted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BootError {
+    /// Reading the device type (0x1000) or identity (0x1018) of the slave failed.
+    Sdo(SdoClientError),
+    /// A slave object differs from the value expected in 0x1F84 - 0x1F88.
+    IdentityMismatch { index: u16, subindex: u8, expected: u32, found: u32 },
+    /// The concise DCF of the slave (0x1F22) is not valid.
+    Dcf(DcfError),
+    /// The configuration of the slave failed.
+    Configuration(SdoClientError),
+}
+
+/// Per-node results of the boot procedure.
+pub struct BootReport {
+    results: [Option<Result<(), BootError>>; 127],
+    /// All mandatory slaves were booted, so the network was started as configured in 0x1F80.
+    pub started: bool,
+}
+
+impl BootReport {
+    /// Result of `node_id`, `None` if it is not assigned as slave in 0x1F81.
+    pub fn result(&self, node_id: u8) -> Option<Result<(), BootError>> {
+        self.results.get((node_id as usize).wrapping_sub(1)).copied().flatten()
+    }
+
+    /// Results of all assigned slaves with their node IDs.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, Result<(), BootError>)> + '_ {
+        self.results.iter().enumerate().filter_map(|(n, result)| result.map(|result| (n as u8 + 1, result)))
+    }
+}
+
+/// Boots the slaves of a CANopen manager as defined by CiA 302-2.
+///
+/// The manager uses the [`SdoClient`] passed to [`Node::nmt_manager`](crate::node::Node::nmt_manager),
+/// the `configure` callback of [`NmtManager::boot`] gets it for its own transfers.
+pub struct NmtManager<'a, 'b, const N: usize, const R: usize> {
+    pub(crate) object_dictionary: &'a Mutex<ThreadModeRawMutex, ObjectDictionary<N>>,
+    pub(crate) sdo_client: SdoClient<'b, R>,
+    pub(crate) nmt_master: NmtMaster<'b, R>,
+    pub(crate) local_nmt: &'b Signal<ThreadModeRawMutex, NmtCommand>,
+}
+
+impl<'a, 'b, const N: usize, const R: usize> NmtManager<'a, 'b, N, R> {
+    /// Boots the slaves assigned in 0x1F81 and starts the network as configured in 0x1F80.
+    ///
+    /// The identity of each slave is checked against 0x1F84 - 0x1F88 (0 = not checked),
+    /// then its concise DCF in 0x1F22 is downloaded and `configure` is called with the node ID
+    /// before the slave is started.
+    /// Nothing is booted unless bit 0 of 0x1F80 marks the node as NMT master.
+    pub async fn boot<F>(&mut self, mut configure: F) -> BootReport
+    where
+        F: AsyncFnMut(&mut SdoClient<'b, R>, u8) -> Result<(), SdoClientError>,
+    {
+        let mut report = BootReport { results: [None; 127], started: false };
+
+        let startup = self.value(0x1F80, 0).await;
+        if startup & 0x01 == 0 {
+            return report;
+        }
+
+        let mut mandatory_failed = false;
+        for node_id in 1..=127u8 {
+            // Bit 0 set = node is a slave, bit 3 set = mandatory slave
+            let assignment = self.value(0x1F81, node_id).await;
+            if assignment & 0x01 == 0 {
+                continue;
+            }
+
+            let mut result = self.check_identity(node_id).await;
+            if result.is_ok() {
+                result = self.download_concise_dcf(node_id).await;
+            }
+            if result.is_ok() {
+                result = configure(&mut self.sdo_client, node_id).await.map_err(BootError::Configuration);
+            }
+
+            match result {
+                Ok(()) => info!("Slave {} booted", node_id),
+                Err(e) => {
+                    warn!("Slave {} not booted: {}", node_id, e);
+                    mandatory_failed |= assignment & 0x08 != 0;
+                }
+            }
+            report.results[node_id as usize - 1] = Some(result);
+        }
+
+        if mandatory_failed {
+            warn!("Mandatory slave not booted, network not started");
+            return report;
+        }
+
+        // Bit 2 set = the manager does not enter operational state itself
+        if startup & 0x04 == 0 {
+            self.local_nmt.signal(NmtCommand::EnterOperational);
+        }
+
+        // Bit 3 set = the slaves are started by the application, bit 1 set = all nodes are started at once
+        if startup & 0x08 == 0 {
+            if startup & 0x02 != 0 {
+                self.nmt_master.send(0, NmtCommand::EnterOperational).await;
+            } else {
+                for (node_id, _) in report.iter().filter(|(_, result)| result.is_ok()) {
+                    self.nmt_master.send(node_id, NmtCommand::EnterOperational).await;
+                }
+            }
+        }
+
+        report.started = true;
+        report
+    }
+
+    /// Returns the SDO client, e.g. to use it once the slaves are booted.
+    pub fn into_sdo_client(self) -> SdoClient<'b, R> {
+        self.sdo_client
+    }
+
+    // Compares device type (0x1000) and identity (0x1018) of the slave with the expected values
+    async fn check_identity(&mut self, node_id: u8) -> Result<(), BootError> {
+        const CHECKS: [(u16, u16, u8); 5] = [
+            (0x1F84, 0x1000, 0),
+            (0x1F85, 0x1018, 1),
+            (0x1F86, 0x1018, 2),
+            (0x1F87, 0x1018, 3),
+            (0x1F88, 0x1018, 4),
+        ];
+
+        for (expected_index, index, subindex) in CHECKS {
+            let expected = self.value(expected_index, node_id).await;
+            // The device type is always read, it shows whether the slave is present
+            if expected == 0 && index != 0x1000 {
+                continue;
+            }
+
+            let data = self.sdo_client.upload::<4>(node_id, index, subindex).await.map_err(BootError::Sdo)?;
+            let mut bytes = [0; 4];
+            bytes[..data.len()].copy_from_slice(&data);
+            let found = u32::from_le_bytes(bytes);
+
+            if expected != 0 && found != expected {
+                return Err(BootError::IdentityMismatch { index, subindex, expected, found });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Writes the entries of the slave's concise DCF (0x1F22) in order, the first failing write aborts the download
+    async fn download_concise_dcf(&mut self, node_id: u8) -> Result<(), BootError> {
+        let mut buffer = [0; MAX_CONCISE_DCF_SIZE];
+        let len = {
+            let locked_od = self.object_dictionary.lock().await;
+            match locked_od.get_value(0x1F22, node_id) {
+                Ok(Value::Domain(domain)) if domain.len() > MAX_CONCISE_DCF_SIZE => return Err(BootError::Dcf(DcfError::TooLarge)),
+                Ok(Value::Domain(domain)) => domain.read(0, &mut buffer),
+                _ => 0,
+            }
+        };
+
+        // An empty domain means no configuration
+        if len == 0 {
+            return Ok(());
+        }
+
+        let dcf = ConciseDcf::parse(&buffer[..len]).map_err(BootError::Dcf)?;
+        for entry in dcf.entries() {
+            self.sdo_client
+                .download(node_id, entry.index, entry.subindex, entry.data)
+                .await
+                .map_err(BootError::Configuration)?;
+        }
+
+        info!("Slave {}: {} concise DCF entries downloaded", node_id, dcf.len());
+        Ok(())
+    }
+
+    async fn value(&self, index: u16, subindex: u8) -> u32 {
+        let locked_od = self.object_dictionary.lock().await;
+        locked_od.get_value(index, subindex).ok().and_then(|value| value.as_u32()).unwrap_or(0)
+    }
+}
@@ -0,0 +1,122 @@
+use core::{cell::RefCell, future::poll_fn, task::Poll};
+
+use embassy_stm32::can::Frame;
+use embassy_sync::{
+    blocking_mutex::{self, raw::ThreadModeRawMutex},
+    channel::Sender,
+    waitqueue::MultiWakerRegistration,
+};
+
+use crate::nmt::{NmtCommand, NmtState};
+
+/// Number of tasks that can wait for remote state changes at the same time.
+pub const MAX_NMT_MASTER_WAITERS: usize = 4;
+
+#[derive(Copy, Clone)]
+struct RemoteNode {
+    state: NmtState,
+    /// Incremented with every boot-up message, so a boot-up is noticed even if a heartbeat follows right away.
+    boot_ups: u8,
+}
+
+struct RemoteNodeTable {
+    nodes: [RemoteNode; 127],
+    wakers: MultiWakerRegistration<MAX_NMT_MASTER_WAITERS>,
+}
+
+/// States of the remote nodes 1 - 127, updated by the node from received heartbeats and boot-up messages.
+pub(crate) struct RemoteNodes {
+    table: blocking_mutex::Mutex<ThreadModeRawMutex, RefCell<RemoteNodeTable>>,
+}
+
+impl RemoteNodes {
+    pub(crate) const fn new() -> Self {
+        Self {
+            table: blocking_mutex::Mutex::new(RefCell::new(RemoteNodeTable {
+                nodes: [RemoteNode { state: NmtState::Unknown, boot_ups: 0 }; 127],
+                wakers: MultiWakerRegistration::new(),
+            })),
+        }
+    }
+
+    /// Records the state reported by `node_id`, a boot-up message reports `Initializing`.
+    pub(crate) fn update(&self, node_id: u8, state: NmtState) {
+        let Some(index) = table_index(node_id) else {
+            return;
+        };
+
+        self.table.lock(|table| {
+            let mut table = table.borrow_mut();
+            let node = &mut table.nodes[index];
+            node.state = state;
+            if state == NmtState::Initializing {
+                node.boot_ups = node.boot_ups.wrapping_add(1);
+            }
+            table.wakers.wake();
+        });
+    }
+
+    fn get(&self, node_id: u8) -> Option<RemoteNode> {
+        let index = table_index(node_id)?;
+        Some(self.table.lock(|table| table.borrow().nodes[index]))
+    }
+
+    // Waits until `done` returns true for the entry of `node_id`, forever if the node ID is not valid
+    async fn wait_until(&self, node_id: u8, mut done: impl FnMut(&RemoteNode) -> bool) {
+        poll_fn(|cx| {
+            self.table.lock(|table| {
+                let mut table = table.borrow_mut();
+                match table_index(node_id) {
+                    Some(index) if done(&table.nodes[index]) => Poll::Ready(()),
+                    _ => {
+                        table.wakers.register(cx.waker());
+                        Poll::Pending
+                    }
+                }
+            })
+        })
+        .await
+    }
+}
+
+fn table_index(node_id: u8) -> Option<usize> {
+    (1..=127).contains(&node_id).then(|| node_id as usize - 1)
+}
+
+/// Commands the NMT states of other nodes and tracks the states they report.
+///
+/// States are taken from the heartbeats and boot-up messages routed by
+/// [`Node::process`](crate::node::Node::process), so the node has to be running.
+#[derive(Copy, Clone)]
+pub struct NmtMaster<'b, const R: usize> {
+    pub(crate) can_tx_sender: Sender<'b, ThreadModeRawMutex, Frame, R>,
+    pub(crate) remote_nodes: &'b RemoteNodes,
+}
+
+impl<const R: usize> NmtMaster<'_, R> {
+    /// Sends `command` to `node_id`, 0 addresses all nodes.
+    pub async fn send(&self, node_id: u8, command: NmtCommand) {
+        let msg = Frame::new_standard(0x000, &[command.into(), node_id]).unwrap();
+        self.can_tx_sender.send(msg).await;
+    }
+
+    /// Last state reported by `node_id`, `Unknown` if the node has not been heard of.
+    pub fn state(&self, node_id: u8) -> NmtState {
+        self.remote_nodes.get(node_id).map_or(NmtState::Unknown, |node| node.state)
+    }
+
+    /// Waits until `node_id` reports `state`, returns right away if it already did.
+    ///
+    /// Combine with [`embassy_time::with_timeout`] to give up on nodes that do not respond.
+    pub async fn wait_for_state(&self, node_id: u8, state: NmtState) {
+        self.remote_nodes.wait_until(node_id, |node| node.state == state).await;
+    }
+
+    /// Waits for the next boot-up message of `node_id`, e.g. after resetting it.
+    ///
+    /// Only boot-ups after the first poll count, so poll it before sending the reset, e.g. with `join`.
+    pub async fn wait_for_boot_up(&self, node_id: u8) {
+        let boot_ups = self.remote_nodes.get(node_id).map_or(0, |node| node.boot_ups);
+        self.remote_nodes.wait_until(node_id, |node| node.boot_ups != boot_ups).await;
+    }
+}
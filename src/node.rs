@@ -1,13 +1,22 @@
 use defmt::{info, warn};
-use embassy_futures::{join, select::select};
+use embassy_futures::{join, select::{select, select4, Either, Either4}};
 use embassy_stm32::{can::{CanRx, CanTx, Frame}};
-use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::{Channel, Receiver, Sender}, mutex::Mutex};
-use embassy_time::{Timer, Duration};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::{Channel, Receiver, Sender}, mutex::Mutex, signal::Signal};
+use embassy_time::{Timer, Duration, Instant};
 use embedded_can::StandardId;
 
-use crate::{nmt::{NmtCommand, NmtState}, node, object_dictionary::ObjectDictionary};
+use crate::{emcy::{EmcyRequest, EMCY_CONSUMER_QUEUE_SIZE, EMCY_QUEUE_SIZE}, event::EVENT_QUEUE_SIZE, heartbeat::{self, HeartbeatConsumerState, LifeGuardingState, TRACKED_HEARTBEATS}, nmt::NmtStateSignals, nmt_master::RemoteNodes, node, object_dictionary::ObjectDictionary, pdo::{self, RpdoState, SyncWindowState, TransmissionType, RPDO_COMMUNICATION, RPDO_MAPPING, TPDO_COMMUNICATION, TRACKED_RPDOS}, sdo::{SdoServer, DEFAULT_SDO_TIMEOUT}, sdo_client::{DEFAULT_SDO_CLIENT_TIMEOUT, SDO_CLIENT_QUEUE_SIZE}, tpdo::{TpdoRequest, SYNC_TX_QUEUE_SIZE, TPDO_QUEUE_SIZE}};
 
+pub use crate::emcy::{EmcyHandle, EmcyMessage, EmcyProducer};
+pub use crate::event::NodeEvent;
 pub use crate::heartbeat::HeartbeatProducer;
+pub use crate::nmt::{NmtCommand, NmtService, NmtState};
+pub use crate::nmt_manager::{BootError, BootReport, NmtManager};
+pub use crate::nmt_master::NmtMaster;
+pub use crate::sdo::AbortCode;
+pub use crate::sdo_client::{SdoClient, SdoClientError};
+pub use crate::sync::SyncProducer;
+pub use crate::tpdo::{TpdoProducer, TpdoTrigger};
 
 pub struct NodeReceiver<'b, const R: usize> {
     can_rx: CanRx<'static>,
@@ -32,18 +41,32 @@ impl <'b, const R: usize> NodeReceiver<'b, R> {
 
 pub struct NodeSender<'b, const R: usize> {
     can_tx: CanTx<'static>,
-    can_tx_receiver: Receiver<'b, ThreadModeRawMutex, embassy_stm32::can::Frame, R>
+    can_tx_receiver: Receiver<'b, ThreadModeRawMutex, embassy_stm32::can::Frame, R>,
+    sync_tx_receiver: Receiver<'b, ThreadModeRawMutex, (Frame, Instant), SYNC_TX_QUEUE_SIZE>,
+    sync_window_expired: &'b Signal<ThreadModeRawMutex, bool>,
 }
 
 impl <'b, const R: usize> NodeSender<'b, R> {
     pub async fn run(&mut self, transmit_timeout: Duration) -> ! {
         loop {
-            let frame = self.can_tx_receiver.receive().await;
+            // Synchronous PDOs go first and are dropped once their synchronous window has passed
+            let (frame, synchronous) = match select(self.sync_tx_receiver.receive(), self.can_tx_receiver.receive()).await {
+                Either::First((frame, window_end)) => {
+                    if Instant::now() > window_end {
+                        self.sync_window_expired.signal(true);
+                        continue;
+                    }
+                    (frame, true)
+                }
+                Either::Second(frame) => (frame, false),
+            };
 
             let timeout = async { Timer::after(transmit_timeout).await };
             let write = self.can_tx.write(&frame);
 
             match select(write, timeout).await {
+                // A synchronous PDO sent within its window clears the synchronous window error
+                embassy_futures::select::Either::First(_) if synchronous => self.sync_window_expired.signal(false),
                 embassy_futures::select::Either::First(_) => (),
                 embassy_futures::select::Either::Second(_) => warn!("Can error: transmit timeout"),
             }
@@ -65,11 +88,70 @@ impl Context {
     }
 }
 
+/// Channels connecting the node with the service handles created from it.
+///
+/// Has to outlive the node and all handles, usually it is a `static`.
+pub struct NodeChannels {
+    sdo_client_responses: Channel<ThreadModeRawMutex, Frame, SDO_CLIENT_QUEUE_SIZE>,
+    tpdo_requests: Channel<ThreadModeRawMutex, TpdoRequest, TPDO_QUEUE_SIZE>,
+    events: Channel<ThreadModeRawMutex, NodeEvent, EVENT_QUEUE_SIZE>,
+    local_sync: Signal<ThreadModeRawMutex, Option<u8>>,
+    sync_tx: Channel<ThreadModeRawMutex, (Frame, Instant), SYNC_TX_QUEUE_SIZE>,
+    /// True when synchronous PDOs were discarded, false when one was sent within its window.
+    sync_window_expired: Signal<ThreadModeRawMutex, bool>,
+    emcy_requests: Channel<ThreadModeRawMutex, EmcyRequest, EMCY_QUEUE_SIZE>,
+    emcy_messages: Channel<ThreadModeRawMutex, EmcyMessage, EMCY_CONSUMER_QUEUE_SIZE>,
+    nmt_state: NmtStateSignals,
+    remote_nodes: RemoteNodes,
+    local_nmt: Signal<ThreadModeRawMutex, NmtCommand>,
+}
+
+impl NodeChannels {
+    pub const fn new() -> Self {
+        Self {
+            sdo_client_responses: Channel::new(),
+            tpdo_requests: Channel::new(),
+            events: Channel::new(),
+            local_sync: Signal::new(),
+            sync_tx: Channel::new(),
+            sync_window_expired: Signal::new(),
+            emcy_requests: Channel::new(),
+            emcy_messages: Channel::new(),
+            nmt_state: NmtStateSignals::new(),
+            remote_nodes: RemoteNodes::new(),
+            local_nmt: Signal::new(),
+        }
+    }
+}
+
+impl Default for NodeChannels {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What the node does on an NMT reset application, after the communication reset.
+#[derive(Copy, Clone)]
+pub enum ApplicationReset<const N: usize> {
+    /// Restores the application objects (0x2000 and above), runs the optional init hook
+    /// and sends [`NodeEvent::ApplicationReset`].
+    Restore(Option<fn(&mut ObjectDictionary<N>)>),
+    /// Resets the whole MCU.
+    SystemReset,
+}
+
 pub struct Node<'a, 'b, 'c, const N: usize, const R: usize> {
     context: &'c Mutex<ThreadModeRawMutex, Context>,
     object_dictionary: &'a Mutex<ThreadModeRawMutex, ObjectDictionary<N>>,
     can_rx_receiver: Receiver<'b, ThreadModeRawMutex, embassy_stm32::can::frame::Envelope, R>,
     can_tx_sender: Sender<'b, ThreadModeRawMutex, embassy_stm32::can::Frame, R>,
+    channels: &'b NodeChannels,
+    sdo_server: SdoServer,
+    rpdos: [RpdoState; TRACKED_RPDOS],
+    sync_window: SyncWindowState,
+    heartbeats: [HeartbeatConsumerState; TRACKED_HEARTBEATS],
+    life_guarding: LifeGuardingState,
+    application_reset: ApplicationReset<N>,
 }
 
 impl<'a, 'b, 'c, const N: usize, const R: usize> Node<'a, 'b, 'c, N, R> {
@@ -79,7 +161,8 @@ impl<'a, 'b, 'c, const N: usize, const R: usize> Node<'a, 'b, 'c, N, R> {
         can_tx: CanTx<'static>,
         can_rx: CanRx<'static>,
         can_rx_channel: &'b Channel<ThreadModeRawMutex, embassy_stm32::can::frame::Envelope, R>,
-        can_tx_channel: &'b Channel<ThreadModeRawMutex, embassy_stm32::can::Frame, R>
+        can_tx_channel: &'b Channel<ThreadModeRawMutex, embassy_stm32::can::Frame, R>,
+        channels: &'b NodeChannels,
     ) -> (Self, NodeReceiver<'b, R>, NodeSender<'b, R>, HeartbeatProducer<'a, 'b, 'c, N, R>) {
         let receiver = NodeReceiver {
             can_rx,
@@ -89,12 +172,15 @@ impl<'a, 'b, 'c, const N: usize, const R: usize> Node<'a, 'b, 'c, N, R> {
         let sender = NodeSender {
             can_tx,
             can_tx_receiver: can_tx_channel.receiver(),
+            sync_tx_receiver: channels.sync_tx.receiver(),
+            sync_window_expired: &channels.sync_window_expired,
         };
 
         let heartbeat_producer = HeartbeatProducer {
             context,
             object_dictionary,
-            can_tx_sender: can_tx_channel.sender()
+            can_tx_sender: can_tx_channel.sender(),
+            nmt_state_changed: &channels.nmt_state.heartbeat,
         };
 
         let node = Self {
@@ -102,11 +188,121 @@ impl<'a, 'b, 'c, const N: usize, const R: usize> Node<'a, 'b, 'c, N, R> {
             context,
             can_rx_receiver: can_rx_channel.receiver(), 
             can_tx_sender: can_tx_channel.sender(), 
+            channels,
+            sdo_server: SdoServer::new(DEFAULT_SDO_TIMEOUT),
+            rpdos: [RpdoState::default(); TRACKED_RPDOS],
+            sync_window: SyncWindowState::default(),
+            heartbeats: [HeartbeatConsumerState::default(); TRACKED_HEARTBEATS],
+            life_guarding: LifeGuardingState::default(),
+            application_reset: ApplicationReset::Restore(None),
         };
 
         (node, receiver, sender, heartbeat_producer)
     }
 
+    /// Sets how long the SDO server waits for the next segment before aborting a transfer.
+    pub fn set_sdo_timeout(&mut self, timeout: Duration) {
+        self.sdo_server.set_timeout(timeout);
+    }
+
+    /// Sets what an NMT reset application does, by default only the application objects are restored.
+    pub fn set_application_reset(&mut self, reset: ApplicationReset<N>) {
+        self.application_reset = reset;
+    }
+
+    /// Creates a client for SDO transfers to other nodes.
+    ///
+    /// All clients share the responses received by the node, so only one should exist.
+    pub fn sdo_client(&self) -> SdoClient<'b, R> {
+        SdoClient {
+            can_tx_sender: self.can_tx_sender,
+            responses: self.channels.sdo_client_responses.receiver(),
+            timeout: DEFAULT_SDO_CLIENT_TIMEOUT,
+        }
+    }
+
+    /// Creates the producer sending the first `T` TPDOs, it has to be run in its own task.
+    pub fn tpdo_producer<const T: usize>(&self) -> TpdoProducer<'a, 'b, 'c, N, R, T> {
+        TpdoProducer {
+            context: self.context,
+            object_dictionary: self.object_dictionary,
+            can_tx_sender: self.can_tx_sender,
+            requests: self.channels.tpdo_requests.receiver(),
+            sync_tx_sender: self.channels.sync_tx.sender(),
+            sync_window_expired: &self.channels.sync_window_expired,
+            nmt_state_changed: &self.channels.nmt_state.tpdo,
+        }
+    }
+
+    /// Creates a handle for signalling application events to the TPDO producer.
+    pub fn tpdo_trigger(&self) -> TpdoTrigger<'b> {
+        TpdoTrigger {
+            requests: self.channels.tpdo_requests.sender(),
+        }
+    }
+
+    /// Creates the producer sending SYNC messages if enabled in 0x1005, it has to be run in its own task.
+    pub fn sync_producer(&self) -> SyncProducer<'a, 'b, 'c, N, R> {
+        SyncProducer {
+            context: self.context,
+            object_dictionary: self.object_dictionary,
+            can_tx_sender: self.can_tx_sender,
+            local_sync: &self.channels.local_sync,
+            nmt_state_changed: &self.channels.nmt_state.sync,
+        }
+    }
+
+    /// Creates the producer sending EMCY messages, it has to be run in its own task.
+    pub fn emcy_producer(&self) -> EmcyProducer<'a, 'b, N, R> {
+        EmcyProducer {
+            object_dictionary: self.object_dictionary,
+            can_tx_sender: self.can_tx_sender,
+            requests: self.channels.emcy_requests.receiver(),
+            nmt_state_changed: &self.channels.nmt_state.emcy,
+        }
+    }
+
+    /// Creates a handle for commanding other nodes and tracking their states.
+    pub fn nmt_master(&self) -> NmtMaster<'b, R> {
+        NmtMaster {
+            can_tx_sender: self.can_tx_sender,
+            remote_nodes: &self.channels.remote_nodes,
+        }
+    }
+
+    /// Creates the CiA 302-2 manager booting the slaves assigned in 0x1F81.
+    ///
+    /// The manager takes the single client of the application, it is given back by [`NmtManager::into_sdo_client`].
+    pub fn nmt_manager(&self, sdo_client: SdoClient<'b, R>) -> NmtManager<'a, 'b, N, R> {
+        NmtManager {
+            object_dictionary: self.object_dictionary,
+            sdo_client,
+            nmt_master: self.nmt_master(),
+            local_nmt: &self.channels.local_nmt,
+        }
+    }
+
+    /// Creates a handle for raising and clearing application errors.
+    pub fn emcy_handle(&self) -> EmcyHandle<'b> {
+        EmcyHandle {
+            requests: self.channels.emcy_requests.sender(),
+        }
+    }
+
+    /// Creates a receiver for the EMCY messages of the nodes configured in 0x1028.
+    ///
+    /// All receivers share the messages, so only one should exist.
+    pub fn emcy_messages(&self) -> Receiver<'b, ThreadModeRawMutex, EmcyMessage, EMCY_CONSUMER_QUEUE_SIZE> {
+        self.channels.emcy_messages.receiver()
+    }
+
+    /// Creates a receiver for the events of the node.
+    ///
+    /// All receivers share the events, so only one should exist.
+    pub fn events(&self) -> Receiver<'b, ThreadModeRawMutex, NodeEvent, EVENT_QUEUE_SIZE> {
+        self.channels.events.receiver()
+    }
+
     // pub fn node_id(&self) -> u8 {
     //     // self.node_id
     // }
@@ -116,52 +312,105 @@ impl<'a, 'b, 'c, const N: usize, const R: usize> Node<'a, 'b, 'c, N, R> {
     // }
 
     pub async fn process(&mut self) -> ! {
+        self.check_configuration().await;
+        self.boot_up().await;
+
         loop {
-            let n = self.can_rx_receiver.receive().await;
+            let n = match select4(
+                self.can_rx_receiver.receive(),
+                Timer::at(self.next_deadline()),
+                self.channels.local_sync.wait(),
+                select(self.channels.sync_window_expired.wait(), self.channels.local_nmt.wait()),
+            )
+            .await
+            {
+                Either4::First(n) => n,
+                Either4::Second(_) => {
+                    self.process_timeouts().await;
+                    continue;
+                }
+                // SYNC sent by the node itself
+                Either4::Third(counter) => {
+                    self.process_sync(counter.as_slice(), Instant::now()).await;
+                    continue;
+                }
+                Either4::Fourth(Either::First(expired)) => {
+                    self.process_sync_window(expired);
+                    continue;
+                }
+                // NMT command of the node's own NMT manager
+                Either4::Fourth(Either::Second(command)) => {
+                    self.process_nmt_command(&[command.into(), 0]).await;
+                    continue;
+                }
+            };
 
+            let received = n.ts;
             let frame = n.frame;
             let cob_id = frame.id();
 
             let node_id;
+            let nmt_state;
             {
                 let locked_context = self.context.lock().await;
                 node_id = locked_context.node_id;
+                nmt_state = locked_context.nmt_state;
+            }
+
+            let sync_cob_id;
+            {
+                let locked_od = self.object_dictionary.lock().await;
+                sync_cob_id = locked_od.get_value(0x1005, 0).ok().and_then(|value| value.as_u32()).map(|value| (value & 0x7FF) as u16);
             }
-            
+
             match cob_id {
                 // Handle NMT command (COB-ID 0x000)
                 embedded_can::Id::Standard(id) if id.as_raw() == 0x000 => {
                     self.process_nmt_command(frame.data()).await;
                 }
 
-                // Process PDOs (example: 0x200 - 0x4FF)
-                embedded_can::Id::Standard(id) if id.as_raw() >= 0x200 && id.as_raw() <= 0x4FF => {
-                    self.process_pdo(frame.data()).await;
-                }
-
                 // Handle SDO (COB-ID 0x600-0x67F for requests and 0x580-0x5FF for responses)
                 embedded_can::Id::Standard(id) if (id.as_raw() == 0x600 + node_id as u16) => {
-                    self.process_sdo_request(frame.data()).await;
+                    if nmt_state.allows(NmtService::Sdo) {
+                        self.process_sdo_request(node_id, frame.data()).await;
+                    }
+                }
+                embedded_can::Id::Standard(id) if (id.as_raw() >= 0x580 && id.as_raw() <= 0x5FF) => {
+                    if nmt_state.allows(NmtService::Sdo) {
+                        self.process_sdo_response(frame);
+                    }
                 }
-                // embedded_can::Id::Standard(id) if (id.as_raw() >= 0x580 && id.as_raw() <= 0x5FF) => {
-                //     self.process_sdo_response(frame.data()).await;
-                // }
 
-                // Handle SYNC message (COB-ID 0x080)
-                embedded_can::Id::Standard(id) if id.as_raw() == 0x080 => {
-                    self.process_sync().await;
+                // Handle SYNC message (COB-ID from 0x1005, 0x080 by default)
+                embedded_can::Id::Standard(id) if Some(id.as_raw()) == sync_cob_id => {
+                    if nmt_state.allows(NmtService::Sync) {
+                        self.process_sync(frame.data(), received).await;
+                    }
                 }
 
-                // Handle Heartbeat message (COB-ID 0x700 + node_id)
+                // Handle Heartbeat message and node guarding RTR (COB-ID 0x700 + node_id)
                 embedded_can::Id::Standard(id) if id.as_raw() >= 0x700 && id.as_raw() <= StandardId::MAX.as_raw() => {
-                    self.process_heartbeat(frame.data()).await;
+                    if !frame.header().rtr() {
+                        self.process_heartbeat((id.as_raw() - 0x700) as u8, frame.data()).await;
+                    } else if id.as_raw() == 0x700 + node_id as u16 {
+                        self.process_node_guarding(node_id).await;
+                    }
                 }
 
-                // Other messages
+                // Other messages, including PDOs whose COB-IDs are configured in 0x1400 + n and 0x1800 + n
+                // and EMCY messages of the nodes configured in 0x1028
                 _ => {
                     match cob_id {
                         embedded_can::Id::Standard(id) => {
-                            info!("Unhandled frame: COB-ID: {}, data: {}", id.as_raw(), frame.data());
+                            let handled = if frame.header().rtr() {
+                                self.process_pdo_request(id.as_raw()).await
+                            } else {
+                                self.process_pdo(id.as_raw(), frame.data()).await || self.process_emcy(id.as_raw(), frame.data()).await
+                            };
+
+                            if !handled {
+                                info!("Unhandled frame: COB-ID: {}, data: {}", id.as_raw(), frame.data());
+                            }
                         }
                         embedded_can::Id::Extended(extended_id) => {
                             info!("Unhandled Extended frame: COB-ID: {}, data: {}", extended_id.as_raw(), frame.data());
@@ -169,8 +418,6 @@ impl<'a, 'b, 'c, const N: usize, const R: usize> Node<'a, 'b, 'c, N, R> {
                     }
                 }
             }
-
-            Timer::after_millis(1).await;
         }
     }
 
@@ -184,69 +431,451 @@ impl<'a, 'b, 'c, const N: usize, const R: usize> Node<'a, 'b, 'c, N, R> {
         let command = NmtCommand::from(data[0]); // Assuming `NmtCommand` can be parsed from the first byte
         let received_node_id = data[1];
 
+        let (node_id, nmt_state) = {
+            let locked_context = self.context.lock().await;
+            (locked_context.node_id, locked_context.nmt_state)
+        };
+        if received_node_id != 0 && received_node_id != node_id {
+            return;
+        }
+
+        let Some(new_state) = nmt_state.transition(command) else {
+            info!("NMT command {:?} not accepted in state {:?}", command, nmt_state);
+            return;
+        };
+
+        match command {
+            NmtCommand::ResetCommunication => self.reset_communication().await,
+            NmtCommand::ResetDevice => self.reset_device().await,
+            _ => self.set_nmt_state(new_state).await,
+        }
+
+        info!("NMT command processed: {:?}, new state: {:?}", command, new_state);
+    }
+
+    // Changes the NMT state and notifies the producers
+    async fn set_nmt_state(&mut self, state: NmtState) {
+        self.context.lock().await.nmt_state = state;
+        self.channels.nmt_state.signal(state);
+
+        // A running transfer must not time out with an abort while SDO is not allowed
+        if !state.allows(NmtService::Sdo) {
+            self.sdo_server.reset();
+        }
+
+        // RPDOs are monitored again once received in operational state
+        if !state.allows(NmtService::Pdo) {
+            if self.rpdos.iter().any(|state| state.timed_out) {
+                self.clear_emcy(0x8250);
+            }
+            self.rpdos = [RpdoState::default(); TRACKED_RPDOS];
+            if self.sync_window.sent() {
+                self.clear_emcy(0x8100);
+            }
+        }
+    }
+
+    // Warns about configured objects the node does not serve completely
+    async fn check_configuration(&self) {
+        let locked_od = self.object_dictionary.lock().await;
+
+        let rpdos = (0..pdo::MAX_PDOS).take_while(|&n| locked_od.get_entry(RPDO_COMMUNICATION + n, 1).is_ok()).count();
+        if rpdos > TRACKED_RPDOS {
+            warn!("Only the first {} of {} RPDOs are monitored and latched on SYNC", TRACKED_RPDOS, rpdos);
+        }
+
+        let heartbeats = locked_od.get_value(0x1016, 0).ok().and_then(|value| value.as_u32()).unwrap_or(0) as usize;
+        if heartbeats > TRACKED_HEARTBEATS {
+            warn!("Only the first {} of {} consumer heartbeat entries are monitored", TRACKED_HEARTBEATS, heartbeats);
+        }
+    }
+
+    // Initializing -> PreOperational transition, at start and after every reset
+    async fn boot_up(&mut self) {
+        self.set_nmt_state(NmtState::Initializing).await;
+        let node_id = self.context.lock().await.node_id;
+        self.object_dictionary.lock().await.apply_node_id(node_id);
+        self.life_guarding = LifeGuardingState::default();
+
+        // The boot-up message is queued before the state change, so it precedes the first heartbeat
+        let msg = Frame::new_standard(0x700 + node_id as u16, &[0x00]).unwrap();
+        self.can_tx_sender.send(msg).await;
+
+        self.set_nmt_state(NmtState::PreOperational).await;
+        info!("Boot-up, node {} pre-operational", node_id);
+    }
+
+    pub async fn heartbear_producer() {
+
+    }
+
+    // Process RPDO (COB-ID from 0x1400 + n), returns false if no RPDO uses `cob_id`
+    async fn process_pdo(&mut self, cob_id: u16, data: &[u8]) -> bool {
+        let nmt_state = self.context.lock().await.nmt_state;
+
+        let mut locked_od = self.object_dictionary.lock().await;
+        let Some((rpdo, _)) = pdo::find_pdo(&locked_od, RPDO_COMMUNICATION, cob_id) else {
+            return false;
+        };
+
+        // PDOs are only processed in operational state
+        if !nmt_state.allows(NmtService::Pdo) {
+            return true;
+        }
+
+        let synchronous = pdo::parameter(&locked_od, RPDO_COMMUNICATION + rpdo, 2)
+            .ok()
+            .and_then(|value| TransmissionType::new(value as u8))
+            .is_some_and(|transmission_type| transmission_type.is_synchronous());
+        let event_timer = pdo::parameter(&locked_od, RPDO_COMMUNICATION + rpdo, 5).unwrap_or(0);
+
+        let Some(state) = self.rpdos.get_mut(rpdo as usize) else {
+            // Not tracked, applied right away
+            if let Err(e) = pdo::unpack(&mut locked_od, RPDO_MAPPING + rpdo, data) {
+                warn!("RPDO {} not processed: {}", rpdo + 1, e);
+            }
+            return true;
+        };
+
+        if synchronous {
+            // Written to the object dictionary with the next SYNC
+            let mut latched = [0u8; 8];
+            let len = data.len().min(8);
+            latched[..len].copy_from_slice(&data[..len]);
+            state.latched = Some((latched, len));
+        } else if let Err(e) = pdo::unpack(&mut locked_od, RPDO_MAPPING + rpdo, data) {
+            warn!("RPDO {} not processed: {}", rpdo + 1, e);
+            return true;
+        }
+
+        // Restart the deadline monitoring, the event timer is in ms (0 = disabled)
+        state.deadline = (event_timer != 0).then(|| Instant::now() + Duration::from_millis(event_timer as u64));
+
+        // The RPDO timeout error is cleared once all RPDOs are received again
+        if core::mem::take(&mut state.timed_out) && !self.rpdos.iter().any(|state| state.timed_out) {
+            self.clear_emcy(0x8250);
+        }
+        true
+    }
+
+    // Process remote request of a TPDO (COB-ID from 0x1800 + n), returns false if no TPDO uses `cob_id`
+    async fn process_pdo_request(&self, cob_id: u16) -> bool {
+        let nmt_state = self.context.lock().await.nmt_state;
+        let tpdo = {
+            let locked_od = self.object_dictionary.lock().await;
+            pdo::find_pdo(&locked_od, TPDO_COMMUNICATION, cob_id)
+        };
+
+        match tpdo {
+            Some((tpdo, pdo_cob_id)) => {
+                if pdo_cob_id.rtr_allowed() && nmt_state.allows(NmtService::Pdo) {
+                    self.request_tpdo(TpdoRequest::Rtr(tpdo));
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn request_tpdo(&self, request: TpdoRequest) {
+        let _ = self.channels.tpdo_requests.try_send(request).inspect_err(|_| warn!("TPDO request dropped"));
+    }
+
+    // Process EMCY message of another node (COB-ID from 0x1028), returns false if no consumer entry uses `cob_id`
+    async fn process_emcy(&self, cob_id: u16, data: &[u8]) -> bool {
+        let node_id = {
+            let locked_od = self.object_dictionary.lock().await;
+            (1..=127u8)
+                .map_while(|subindex| locked_od.get_value(0x1028, subindex).ok().map(|value| (subindex, value.as_u32().unwrap_or(0x8000_0000))))
+                .find(|&(_, consumer)| consumer & 0x8000_0000 == 0 && consumer & 0x7FF == cob_id as u32)
+                .map(|(subindex, _)| subindex)
+        };
+
+        let Some(node_id) = node_id else {
+            return false;
+        };
+
+        if !self.context.lock().await.nmt_state.allows(NmtService::Emcy) {
+            return true;
+        }
+
+        let Ok(data) = <&[u8; 8]>::try_from(data) else {
+            info!("Invalid EMCY frame");
+            return true;
+        };
+
+        let message = EmcyMessage::new(node_id, data);
+        let _ = self.channels.emcy_messages.try_send(message).inspect_err(|_| warn!("EMCY message dropped"));
+        true
+    }
+
+    // Process SDO request (COB-ID: 0x600 + node_id), the response is sent on 0x580 + node_id
+    async fn process_sdo_request(&mut self, node_id: u8, data: &[u8]) {
+        let Ok(request) = <&[u8; 8]>::try_from(data) else {
+            info!("Invalid SDO frame");
+            return;
+        };
+
+        let response = {
+            let mut locked_od = self.object_dictionary.lock().await;
+            self.sdo_server.process_request(&mut locked_od, request, Instant::now())
+        };
+
+        if let Some(response) = response {
+            self.send_sdo_response(node_id, &response).await;
+        }
+
+        // A block upload sends its segments without waiting for further requests
+        loop {
+            let segment = {
+                let locked_od = self.object_dictionary.lock().await;
+                self.sdo_server.next_block_segment(&locked_od, Instant::now())
+            };
+
+            match segment {
+                Some(segment) => self.send_sdo_response(node_id, &segment).await,
+                None => break,
+            }
+        }
+    }
+
+    async fn send_sdo_response(&self, node_id: u8, response: &[u8; 8]) {
+        let msg = Frame::new_standard(0x580 + node_id as u16, response).unwrap();
+        self.can_tx_sender.send(msg).await;
+    }
+
+    // Process SDO response (COB-ID: 0x580 - 0x5FF) by handing it to the SDO client
+    fn process_sdo_response(&self, frame: Frame) {
+        let _ = self.channels.sdo_client_responses.try_send(frame).inspect_err(|_| warn!("SDO client response dropped"));
+    }
+
+    // Process SYNC message (COB-ID from 0x1005), the optional data byte is the SYNC counter
+    async fn process_sync(&mut self, data: &[u8], received: Instant) {
+        // Apply the data of synchronous RPDOs received since the last SYNC
         {
-            let mut locked_context = self.context.lock().await;
-            if received_node_id == 0 || received_node_id == locked_context.node_id {
-                match command {
-                    NmtCommand::EnterOperational => locked_context.nmt_state = NmtState::Operational,
-                    NmtCommand::EnterStopped => locked_context.nmt_state = NmtState::Stopped,
-                    NmtCommand::EnterPreOperational => locked_context.nmt_state = NmtState::PreOperational,
-                    NmtCommand::ResetCommunication => self.reset_communication(),
-                    NmtCommand::ResetDevice => self.reset_device(),
-                    _ => info!("Unknown NMT command"),
+            let mut locked_od = self.object_dictionary.lock().await;
+            for (rpdo, state) in self.rpdos.iter_mut().enumerate() {
+                if let Some((data, len)) = state.latched.take() {
+                    if let Err(e) = pdo::unpack(&mut locked_od, RPDO_MAPPING + rpdo as u16, &data[..len]) {
+                        warn!("RPDO {} not processed: {}", rpdo + 1, e);
+                    }
                 }
+            }
+        }
+
+        // Synchronous window length in us (0x1007), 0 = no window
+        let window_length = {
+            let locked_od = self.object_dictionary.lock().await;
+            locked_od.get_value(0x1007, 0).ok().and_then(|value| value.as_u32()).unwrap_or(0)
+        };
+        let window_end = (window_length != 0).then(|| received + Duration::from_micros(window_length as u64));
+
+        self.request_tpdo(TpdoRequest::Sync { counter: data.first().copied(), window_end });
+    }
 
-                info!("NMT command processed: {:?}, new state: {:?}", command, locked_context.nmt_state);
+    // Synchronous TPDOs were discarded because the synchronous window (0x1007) had passed, or one was sent within it.
+    // The error is raised once and cleared by the next synchronous TPDO sent in time.
+    fn process_sync_window(&mut self, expired: bool) {
+        if !expired {
+            if self.sync_window.sent() {
+                info!("Synchronous PDOs sent within the synchronous window again");
+                self.clear_emcy(0x8100);
             }
+            return;
+        }
+
+        warn!("Synchronous PDOs discarded after the synchronous window");
+        if self.sync_window.expired() {
+            self.raise_emcy(0x8100);
         }
     }
 
-    pub async fn heartbear_producer() {
+    // Process Heartbeat message (COB-ID: 0x700 + node_id) of a node monitored in 0x1016
+    async fn process_heartbeat(&mut self, remote_node_id: u8, data: &[u8]) {
+        let Some(&state) = data.first() else {
+            info!("Invalid Heartbeat frame");
+            return;
+        };
+        // Bit 7 is the toggle bit of node guarding responses
+        let state = NmtState::from(state & 0x7F);
+        self.channels.remote_nodes.update(remote_node_id, state);
+
+        let entry = {
+            let locked_od = self.object_dictionary.lock().await;
+            (1..=TRACKED_HEARTBEATS as u8)
+                .map_while(|subindex| locked_od.get_value(0x1016, subindex).ok().map(|value| (subindex, value.as_u32().unwrap_or(0))))
+                .map(|(subindex, value)| (subindex, heartbeat::consumer_entry(value)))
+                .find(|&(_, (node_id, time))| node_id == remote_node_id && time != 0)
+        };
+        let Some((subindex, (_, time))) = entry else {
+            return;
+        };
+
+        let consumer = &mut self.heartbeats[subindex as usize - 1];
+        consumer.node_id = remote_node_id;
+        consumer.deadline = Some(Instant::now() + Duration::from_millis(time as u64));
+        let recovered = core::mem::take(&mut consumer.lost);
+        let state_changed = core::mem::replace(&mut consumer.state, state) != state;
+
+        if recovered {
+            info!("Heartbeat of node {} recovered", remote_node_id);
+            self.send_event(NodeEvent::HeartbeatRecovered(remote_node_id));
+            if !self.guarding_error_active() {
+                self.clear_emcy(0x8130);
+            }
+        }
+        if state_changed {
+            self.send_event(NodeEvent::RemoteStateChanged { node_id: remote_node_id, state });
+        }
+    }
+
+    // Process node guarding RTR (COB-ID: 0x700 + node_id), answered with the NMT state and the toggle bit
+    async fn process_node_guarding(&mut self, node_id: u8) {
+        let (heartbeat_time, guard_time, life_time_factor) = {
+            let locked_od = self.object_dictionary.lock().await;
+            let value = |index| locked_od.get_value(index, 0).ok().and_then(|value| value.as_u32()).unwrap_or(0);
+            (value(0x1017), value(0x100C), value(0x100D))
+        };
+
+        // Node guarding is only used while the heartbeat producer is disabled
+        if heartbeat_time != 0 {
+            return;
+        }
+
+        let state: u8 = self.context.lock().await.nmt_state.into();
+        let response = state | ((self.life_guarding.toggle as u8) << 7);
+        self.life_guarding.toggle = !self.life_guarding.toggle;
+
+        let msg = Frame::new_standard(0x700 + node_id as u16, &[response]).unwrap();
+        self.can_tx_sender.send(msg).await;
+
+        // Life guarding, the life time is in ms (0 = disabled)
+        let life_time = guard_time as u64 * life_time_factor as u64;
+        self.life_guarding.deadline = (life_time != 0).then(|| Instant::now() + Duration::from_millis(life_time));
 
+        if core::mem::take(&mut self.life_guarding.failed) {
+            info!("Life guarding resumed");
+            self.send_event(NodeEvent::LifeGuardingResumed);
+            if !self.guarding_error_active() {
+                self.clear_emcy(0x8130);
+            }
+        }
     }
 
-    // Placeholder for processing PDOs (COB-IDs: 0x200 - 0x4FF)
-    async fn process_pdo(&self, _data: &[u8]) {
-        info!("Processing PDO");
-        // PDO logic here
+    // Heartbeat consumers and life guarding share the EMCY error code 0x8130
+    fn guarding_error_active(&self) -> bool {
+        self.life_guarding.failed || self.heartbeats.iter().any(|consumer| consumer.lost)
     }
 
-    // Placeholder for processing SDO request (COB-ID: 0x600 - 0x67F)
-    async fn process_sdo_request(&self, _data: &[u8]) {
-        info!("Processing SDO Request");
-        // SDO request logic here
+    // Earliest deadline of the services handled by the node
+    fn next_deadline(&self) -> Instant {
+        self.rpdos
+            .iter()
+            .filter_map(|state| state.deadline)
+            .chain(self.heartbeats.iter().filter_map(|consumer| consumer.deadline))
+            .chain(self.life_guarding.deadline)
+            .chain(self.sdo_server.deadline())
+            .min()
+            .unwrap_or(Instant::MAX)
     }
 
-    // Placeholder for processing SDO response (COB-ID: 0x580 - 0x5FF)
-    async fn process_sdo_response(&self, _data: &[u8]) {
-        info!("Processing SDO Response");
-        // SDO response logic here
+    // Handle services whose deadline elapsed while waiting for frames
+    async fn process_timeouts(&mut self) {
+        let node_id = self.context.lock().await.node_id;
+        let now = Instant::now();
+
+        if let Some(response) = self.sdo_server.process_timeout(now) {
+            warn!("SDO transfer timed out");
+            self.send_sdo_response(node_id, &response).await;
+        }
+
+        for rpdo in 0..TRACKED_RPDOS {
+            if self.rpdos[rpdo].deadline.is_some_and(|deadline| deadline <= now) {
+                self.rpdos[rpdo].deadline = None;
+                warn!("RPDO {} timed out", rpdo + 1);
+                self.send_event(NodeEvent::RpdoTimeout(rpdo as u16));
+                self.rpdos[rpdo].timed_out = true;
+                self.raise_emcy(0x8250);
+            }
+        }
+
+        for consumer in 0..TRACKED_HEARTBEATS {
+            if self.heartbeats[consumer].deadline.is_some_and(|deadline| deadline <= now) {
+                let remote_node_id = self.heartbeats[consumer].node_id;
+                self.heartbeats[consumer] = HeartbeatConsumerState { node_id: remote_node_id, lost: true, ..Default::default() };
+                warn!("Heartbeat of node {} lost", remote_node_id);
+                self.send_event(NodeEvent::HeartbeatLost(remote_node_id));
+                self.raise_emcy(0x8130);
+            }
+        }
+
+        if self.life_guarding.deadline.is_some_and(|deadline| deadline <= now) {
+            self.life_guarding.deadline = None;
+            self.life_guarding.failed = true;
+            warn!("Life guarding failed");
+            self.send_event(NodeEvent::LifeGuardingFailed);
+            self.raise_emcy(0x8130);
+        }
     }
 
-    // Process SYNC message (COB-ID: 0x080)
-    async fn process_sync(&self) {
-        info!("Processing SYNC message");
-        // SYNC message handling logic here
+    fn send_event(&self, event: NodeEvent) {
+        let _ = self.channels.events.try_send(event).inspect_err(|_| warn!("Node event dropped"));
     }
 
-    // Process Heartbeat message (COB-ID: 0x700 + node_id)
-    async fn process_heartbeat(&self, _data: &[u8]) {
-        info!("Processing Heartbeat message");
-        // Heartbeat message handling logic here
+    // Raise an error of the node, reported by the EMCY producer
+    fn raise_emcy(&self, error_code: u16) {
+        let request = EmcyRequest::Raise { error_code, data: [0; 5] };
+        let _ = self.channels.emcy_requests.try_send(request).inspect_err(|_| warn!("EMCY {} dropped", error_code));
     }
 
-    // Node reset function for NMT ResetNode command
-    fn reset_communication(&mut self) {
-        // self.nmt_state = NmtState::Initializing;
-        // Logic to reset the node state, reinitialize services, etc.
-        info!("Node reset");
+    fn clear_emcy(&self, error_code: u16) {
+        let _ = self.channels.emcy_requests.try_send(EmcyRequest::Clear(error_code)).inspect_err(|_| warn!("EMCY reset dropped"));
     }
 
-    // Node reset function for NMT ResetNode command
-    fn reset_device(&mut self) {
-        // self.nmt_state = NmtState::Initializing;
-        // Logic to reset the node state, reinitialize services, etc.
-        info!("Node reset");
+    // Node reset function for NMT ResetCommunication command, restores the communication area (0x1000 - 0x1FFF)
+    async fn reset_communication(&mut self) {
+        info!("Communication reset");
+        self.reset_communication_state();
+        self.object_dictionary.lock().await.restore_defaults(0x1000..=0x1FFF);
+        self.boot_up().await;
+    }
+
+    // Node reset function for NMT ResetNode command, additionally restores the application objects (0x2000 and above)
+    async fn reset_device(&mut self) {
+        info!("Application reset");
+        let hook = match self.application_reset {
+            ApplicationReset::Restore(hook) => hook,
+            ApplicationReset::SystemReset => cortex_m::peripheral::SCB::sys_reset(),
+        };
+
+        self.reset_communication_state();
+        {
+            let mut locked_od = self.object_dictionary.lock().await;
+            locked_od.restore_defaults(0x1000..=0xFFFF);
+            if let Some(hook) = hook {
+                hook(&mut locked_od);
+            }
+        }
+        self.send_event(NodeEvent::ApplicationReset);
+        self.boot_up().await;
+    }
+
+    // Drops the state of the communication services, errors reported by the node are cleared
+    fn reset_communication_state(&mut self) {
+        if self.rpdos.iter().any(|state| state.timed_out) {
+            self.clear_emcy(0x8250);
+        }
+        if self.guarding_error_active() {
+            self.clear_emcy(0x8130);
+        }
+        if self.sync_window.sent() {
+            self.clear_emcy(0x8100);
+        }
+
+        self.sdo_server.reset();
+        self.rpdos = [RpdoState::default(); TRACKED_RPDOS];
+        self.heartbeats = [HeartbeatConsumerState::default(); TRACKED_HEARTBEATS];
+        self.life_guarding = LifeGuardingState::default();
     }
 }
 
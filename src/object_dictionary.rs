@@ -1,7 +1,10 @@
-use core::usize;
+use core::{cell::RefCell, cmp::Ordering, fmt, ops::RangeInclusive};
 
+use embassy_sync::blocking_mutex::{self, raw::CriticalSectionRawMutex};
 use heapless::FnvIndexMap;
 
+use crate::pdo;
+
 #[allow(unused)]
 pub struct ObjectDictionaryEntry {
     index: u16,
@@ -9,9 +12,15 @@ pub struct ObjectDictionaryEntry {
     data_type: DataType,
     access_type: AccessType,
     pub(crate) value: Value,
+    default: Value,
+    limits: Option<(Value, Value)>,
+    node_id_base: Option<u32>,
+    pdo_mappable: bool,
 }
 
 #[allow(unused)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum DataType {
     Boolean,
     Integer8,
@@ -21,6 +30,21 @@ pub enum DataType {
     Unsigned16,
     Unsigned32,
     Float32,
+    VisibleString,
+    OctetString,
+    Domain,
+}
+
+impl DataType {
+    /// Size of an encoded value of this type in bytes, `None` for variable length types.
+    pub fn size(&self) -> Option<usize> {
+        match self {
+            DataType::Boolean | DataType::Integer8 | DataType::Unsigned8 => Some(1),
+            DataType::Integer16 | DataType::Unsigned16 => Some(2),
+            DataType::Integer32 | DataType::Unsigned32 | DataType::Float32 => Some(4),
+            DataType::VisibleString | DataType::OctetString | DataType::Domain => None,
+        }
+    }
 }
 
 #[allow(unused)]
@@ -42,50 +66,370 @@ pub enum Value {
     Uint16(u16),
     Uint32(u32),
     Float32(f32),
+    VisibleString(&'static str),
+    Domain(&'static Domain),
 }
 
-#[derive(Copy, Clone, Debug)]
+impl Value {
+    /// Size of the encoded value in bytes.
+    pub fn size(&self) -> usize {
+        match *self {
+            Value::Bool(_) | Value::Int8(_) | Value::Uint8(_) => 1,
+            Value::Int16(_) | Value::Uint16(_) => 2,
+            Value::Int32(_) | Value::Uint32(_) | Value::Float32(_) => 4,
+            Value::VisibleString(s) => s.len(),
+            Value::Domain(d) => d.len(),
+        }
+    }
+
+    /// Copies the little-endian encoding of the value, starting at `offset`, into `buf`.
+    /// Returns the number of bytes copied.
+    pub fn read_bytes(&self, offset: usize, buf: &mut [u8]) -> usize {
+        fn copy(bytes: &[u8], offset: usize, buf: &mut [u8]) -> usize {
+            let bytes = bytes.get(offset..).unwrap_or(&[]);
+            let len = bytes.len().min(buf.len());
+            buf[..len].copy_from_slice(&bytes[..len]);
+            len
+        }
+
+        match *self {
+            Value::Bool(v) => copy(&[v as u8], offset, buf),
+            Value::Int8(v) => copy(&v.to_le_bytes(), offset, buf),
+            Value::Int16(v) => copy(&v.to_le_bytes(), offset, buf),
+            Value::Int32(v) => copy(&v.to_le_bytes(), offset, buf),
+            Value::Uint8(v) => copy(&v.to_le_bytes(), offset, buf),
+            Value::Uint16(v) => copy(&v.to_le_bytes(), offset, buf),
+            Value::Uint32(v) => copy(&v.to_le_bytes(), offset, buf),
+            Value::Float32(v) => copy(&v.to_le_bytes(), offset, buf),
+            Value::VisibleString(s) => copy(s.as_bytes(), offset, buf),
+            Value::Domain(d) => d.read(offset, buf),
+        }
+    }
+
+    /// The value of unsigned and boolean entries widened to `u32`.
+    pub fn as_u32(&self) -> Option<u32> {
+        match *self {
+            Value::Bool(v) => Some(v as u32),
+            Value::Uint8(v) => Some(v as u32),
+            Value::Uint16(v) => Some(v as u32),
+            Value::Uint32(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Whether the value can be stored in an entry of the given type.
+    pub fn matches(&self, data_type: DataType) -> bool {
+        matches!(
+            (self, data_type),
+            (Value::Bool(_), DataType::Boolean)
+                | (Value::Int8(_), DataType::Integer8)
+                | (Value::Int16(_), DataType::Integer16)
+                | (Value::Int32(_), DataType::Integer32)
+                | (Value::Uint8(_), DataType::Unsigned8)
+                | (Value::Uint16(_), DataType::Unsigned16)
+                | (Value::Uint32(_), DataType::Unsigned32)
+                | (Value::Float32(_), DataType::Float32)
+                | (Value::VisibleString(_), DataType::VisibleString)
+                | (Value::Domain(_), DataType::VisibleString | DataType::OctetString | DataType::Domain)
+        )
+    }
+
+    /// Compares two numeric values of the same type, `None` for anything else.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Int8(a), Value::Int8(b)) => a.partial_cmp(b),
+            (Value::Int16(a), Value::Int16(b)) => a.partial_cmp(b),
+            (Value::Int32(a), Value::Int32(b)) => a.partial_cmp(b),
+            (Value::Uint8(a), Value::Uint8(b)) => a.partial_cmp(b),
+            (Value::Uint16(a), Value::Uint16(b)) => a.partial_cmp(b),
+            (Value::Uint32(a), Value::Uint32(b)) => a.partial_cmp(b),
+            (Value::Float32(a), Value::Float32(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+
+    /// Decodes a little-endian value of the given fixed size type.
+    /// Returns `None` if the length of `data` does not match the type.
+    pub fn from_le_bytes(data_type: DataType, data: &[u8]) -> Option<Value> {
+        if Some(data.len()) != data_type.size() {
+            return None;
+        }
+
+        let value = match data_type {
+            DataType::Boolean => Value::Bool(data[0] != 0),
+            DataType::Integer8 => Value::Int8(data[0] as i8),
+            DataType::Integer16 => Value::Int16(i16::from_le_bytes([data[0], data[1]])),
+            DataType::Integer32 => Value::Int32(i32::from_le_bytes([data[0], data[1], data[2], data[3]])),
+            DataType::Unsigned8 => Value::Uint8(data[0]),
+            DataType::Unsigned16 => Value::Uint16(u16::from_le_bytes([data[0], data[1]])),
+            DataType::Unsigned32 => Value::Uint32(u32::from_le_bytes([data[0], data[1], data[2], data[3]])),
+            DataType::Float32 => Value::Float32(f32::from_le_bytes([data[0], data[1], data[2], data[3]])),
+            DataType::VisibleString | DataType::OctetString | DataType::Domain => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Application provided storage for variable length entries (strings, octet strings and domains).
+///
+/// The buffer is borrowed for `'static` so a `Value::Domain` stays `Copy` while its content
+/// can be written through the object dictionary, e.g. by segmented SDO transfers.
+///
+/// SDO downloads write the content in place, so an aborted or timed out download destroys the stored data.
+pub struct Domain {
+    buffer: blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<DomainBuffer>>,
+}
+
+struct DomainBuffer {
+    data: &'static mut [u8],
+    len: usize,
+}
+
+impl Domain {
+    /// Creates an empty domain backed by `data`.
+    pub const fn new(data: &'static mut [u8]) -> Self {
+        Self::with_len(data, 0)
+    }
+
+    /// Creates a domain backed by `data` whose first `len` bytes are valid.
+    pub const fn with_len(data: &'static mut [u8], len: usize) -> Self {
+        Self {
+            buffer: blocking_mutex::Mutex::new(RefCell::new(DomainBuffer { data, len })),
+        }
+    }
+
+    /// Number of valid bytes.
+    pub fn len(&self) -> usize {
+        self.buffer.lock(|b| b.borrow().len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maximum number of bytes the domain can hold.
+    pub fn capacity(&self) -> usize {
+        self.buffer.lock(|b| b.borrow().data.len())
+    }
+
+    /// Copies the valid bytes starting at `offset` into `buf`. Returns the number of bytes copied.
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> usize {
+        self.buffer.lock(|b| {
+            let b = b.borrow();
+            let valid = b.data[..b.len].get(offset..).unwrap_or(&[]);
+            let len = valid.len().min(buf.len());
+            buf[..len].copy_from_slice(&valid[..len]);
+            len
+        })
+    }
+
+    /// Writes `data` at `offset`, extending the valid length if needed.
+    pub fn write(&self, offset: usize, data: &[u8]) -> Result<(), ReadWriteError> {
+        self.buffer.lock(|b| {
+            let mut b = b.borrow_mut();
+            let end = offset + data.len();
+            if end > b.data.len() {
+                return Err(ReadWriteError::LengthTooHigh);
+            }
+
+            b.data[offset..end].copy_from_slice(data);
+            b.len = b.len.max(end);
+            Ok(())
+        })
+    }
+
+    /// Sets the number of valid bytes, e.g. after a complete transfer.
+    pub fn set_len(&self, len: usize) -> Result<(), ReadWriteError> {
+        self.buffer.lock(|b| {
+            let mut b = b.borrow_mut();
+            if len > b.data.len() {
+                return Err(ReadWriteError::LengthTooHigh);
+            }
+
+            b.len = len;
+            Ok(())
+        })
+    }
+}
+
+impl fmt::Debug for Domain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Domain").field("len", &self.len()).field("capacity", &self.capacity()).finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Domain {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Domain {{ len: {}, capacity: {} }}", self.len(), self.capacity())
+    }
+}
+
+/// Errors accessing the object dictionary.
+///
+/// Every variant corresponds to a CiA 301 SDO abort code, so a failed access
+/// can be reported to the SDO client as is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ReadWriteError {
     // Cannot read from a write-only entry.
-    AccessDenied,
+    WriteOnly,
+    // Cannot write to a read-only entry.
+    ReadOnly,
+    // Access not supported by the entry, e.g. writing variable length data without storage.
+    UnsupportedAccess,
+    // No entry with this index.
+    ObjectDoesNotExist,
+    // The index exists but not the subindex.
+    SubindexDoesNotExist,
+    // The object cannot be mapped to a PDO.
+    NotMappable,
+    // The mapped objects exceed the PDO length.
+    PdoLengthExceeded,
+    // General parameter incompatibility.
+    ParameterIncompatibility,
+    // General internal incompatibility in the device.
+    InternalIncompatibility,
+    // Access failed due to a hardware error.
+    HardwareError,
+    // Data type does not match or length does not match.
+    LengthMismatch,
+    // Data does not fit into the entry.
+    LengthTooHigh,
+    // Data is shorter than the entry.
+    LengthTooLow,
+    // Value not valid for the entry.
+    ValueRangeExceeded,
+    // Value above the upper limit of the entry.
+    ValueTooHigh,
+    // Value below the lower limit of the entry.
+    ValueTooLow,
+    // Maximum value is less than minimum value.
+    MaxLessThanMin,
+    // Data cannot be stored because of local control.
+    LocalControl,
+    // Data cannot be stored in the present device state.
+    WrongDeviceState,
+    // No data available.
+    NoDataAvailable,
+    // Resource not available.
+    OutOfMemory,
 }
 
 impl ObjectDictionaryEntry {
-    #[allow(unused)]
-    fn read(&self) -> Result<Value, ReadWriteError> {
-        if matches!(
-            self.access_type,
-            AccessType::ReadOnly | AccessType::ReadWrite
-        ) {
+    pub fn new(index: u16, subindex: u8, data_type: DataType, access_type: AccessType, value: Value) -> Self {
+        Self {
+            index,
+            subindex,
+            data_type,
+            access_type,
+            value,
+            default: value,
+            limits: None,
+            node_id_base: None,
+            pdo_mappable: false,
+        }
+    }
+
+    /// Marks the entry as a COB-ID of the pre-defined connection set.
+    /// Its value becomes `base + node_id` once the node ID is applied.
+    pub fn with_node_id_base(mut self, base: u32) -> Self {
+        self.node_id_base = Some(base);
+        self
+    }
+
+    /// Allows the entry to be mapped into PDOs.
+    pub fn with_pdo_mapping(mut self) -> Self {
+        self.pdo_mappable = true;
+        self
+    }
+
+    /// Restricts written values to `min..=max`.
+    pub fn with_limits(mut self, min: Value, max: Value) -> Self {
+        self.limits = Some((min, max));
+        self
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn is_pdo_mappable(&self) -> bool {
+        self.pdo_mappable
+    }
+
+    pub(crate) fn read(&self) -> Result<Value, ReadWriteError> {
+        if self.is_readable() {
             Ok(self.value)
         } else {
-            Err(ReadWriteError::AccessDenied)
+            Err(ReadWriteError::WriteOnly)
         }
     }
 
-    #[allow(unused)]
-    fn write(&mut self, new_value: Value) -> Result<(), ReadWriteError> {
-        if matches!(
-            self.access_type,
-            AccessType::WriteOnly | AccessType::ReadWrite
-        ) {
-            self.value = new_value;
-            Ok(())
-        } else {
-            Err(ReadWriteError::AccessDenied)
+    pub(crate) fn is_readable(&self) -> bool {
+        matches!(self.access_type, AccessType::ReadOnly | AccessType::ReadWrite)
+    }
+
+    pub(crate) fn is_writable(&self) -> bool {
+        matches!(self.access_type, AccessType::WriteOnly | AccessType::ReadWrite)
+    }
+
+    pub(crate) fn write(&mut self, new_value: Value) -> Result<(), ReadWriteError> {
+        if !self.is_writable() {
+            return Err(ReadWriteError::ReadOnly);
+        }
+
+        if !new_value.matches(self.data_type) {
+            return Err(ReadWriteError::LengthMismatch);
         }
+
+        if let Some((min, max)) = self.limits {
+            if new_value.compare(&min) == Some(Ordering::Less) {
+                return Err(ReadWriteError::ValueTooLow);
+            }
+            if new_value.compare(&max) == Some(Ordering::Greater) {
+                return Err(ReadWriteError::ValueTooHigh);
+            }
+        }
+
+        self.value = new_value;
+        Ok(())
     }
 }
 
 
 pub struct Config {
-    
+    /// Number of receive PDOs (0x1400 / 0x1600 entries), at most 512.
+    ///
+    /// The node monitors the event timer and latches synchronous data of the first 32 only,
+    /// the others are applied on reception.
+    pub rpdo_count: u16,
+    /// Number of transmit PDOs (0x1800 / 0x1A00 entries), at most 512.
+    pub tpdo_count: u16,
+    /// Number of errors kept in the pre-defined error field (0x1003), at most 254.
+    pub error_history_depth: u8,
+    /// Number of EMCY consumer entries (0x1028), at most 127.
+    pub emcy_consumer_count: u8,
+    /// Number of consumer heartbeat entries (0x1016), at most 127.
+    ///
+    /// The node monitors the first 32 only.
+    pub heartbeat_consumer_count: u8,
+    /// Number of slaves the node can boot as NMT manager (0x1F81, 0x1F84 - 0x1F88), at most 127.
+    ///
+    /// NMT resets restore the defaults of 0x1F80 - 0x1F88, so the application sets them with
+    /// [`ObjectDictionary::set_default`] rather than [`ObjectDictionary::write`].
+    pub boot_slave_count: u8,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            rpdo_count: 4,
+            tpdo_count: 4,
+            error_history_depth: 8,
+            emcy_consumer_count: 4,
+            heartbeat_consumer_count: 4,
+            boot_slave_count: 0,
         }
     }
 }
@@ -103,9 +447,119 @@ impl<const N: usize> ObjectDictionary<N> {
         self.entries.insert((entry.index, entry.subindex), entry);
     }
 
-    #[allow(unused)]
-    pub fn get_entry(&self, index: u16, subindex: u8) -> Option<&ObjectDictionaryEntry> {
-        self.entries.get(&(index, subindex))
+    pub fn get_entry(&self, index: u16, subindex: u8) -> Result<&ObjectDictionaryEntry, ReadWriteError> {
+        match self.entries.get(&(index, subindex)) {
+            Some(entry) => Ok(entry),
+            None => Err(self.missing_entry_error(index)),
+        }
+    }
+
+    pub fn get_entry_mut(&mut self, index: u16, subindex: u8) -> Result<&mut ObjectDictionaryEntry, ReadWriteError> {
+        if self.entries.contains_key(&(index, subindex)) {
+            Ok(&mut self.entries[&(index, subindex)])
+        } else {
+            Err(self.missing_entry_error(index))
+        }
+    }
+
+    /// Current value of an entry, regardless of its access type.
+    pub fn get_value(&self, index: u16, subindex: u8) -> Result<Value, ReadWriteError> {
+        self.get_entry(index, subindex).map(|entry| entry.value)
+    }
+
+    /// Writes an entry the way a remote node would, so PDO parameters are only changed consistently.
+    pub fn write(&mut self, index: u16, subindex: u8, value: Value) -> Result<(), ReadWriteError> {
+        pdo::check_parameter_write(self, index, subindex, value)?;
+
+        // Writing 0 to the number of errors clears the error history, other values are not allowed
+        if (index, subindex) == (0x1003, 0) {
+            if value.as_u32() != Some(0) {
+                return Err(ReadWriteError::ValueRangeExceeded);
+            }
+            for subindex in 1..=u8::MAX {
+                match self.get_entry_mut(0x1003, subindex) {
+                    Ok(entry) => entry.value = Value::Uint32(0),
+                    Err(_) => break,
+                }
+            }
+        }
+
+        // The SYNC counter overflow value 1 is reserved
+        if (index, subindex) == (0x1019, 0) && value.as_u32() == Some(1) {
+            return Err(ReadWriteError::ValueRangeExceeded);
+        }
+
+        self.get_entry_mut(index, subindex)?.write(value)
+    }
+
+    /// Adds an error to the pre-defined error field (0x1003), the newest error is at sub-index 1.
+    /// `error` holds the error code in bits 0 - 15 and the manufacturer specific additional information in bits 16 - 31.
+    /// The oldest error is dropped once the history is full.
+    pub fn push_error_history(&mut self, error: u32) {
+        let Ok(count) = self.get_value(0x1003, 0) else {
+            return;
+        };
+
+        let mut depth = 0;
+        let mut previous = Value::Uint32(error);
+        while let Ok(entry) = self.get_entry_mut(0x1003, depth + 1) {
+            previous = core::mem::replace(&mut entry.value, previous);
+            depth += 1;
+        }
+
+        let count = (count.as_u32().unwrap_or(0) as u8).saturating_add(1).min(depth);
+        if let Ok(entry) = self.get_entry_mut(0x1003, 0) {
+            entry.value = Value::Uint8(count);
+        }
+    }
+
+    /// Sets the value of an entry together with the default restored on NMT resets,
+    /// e.g. for the boot slave configuration (0x1F80 - 0x1F88) changed by the application at runtime.
+    pub fn set_default(&mut self, index: u16, subindex: u8, value: Value) -> Result<(), ReadWriteError> {
+        let entry = self.get_entry_mut(index, subindex)?;
+        if !value.matches(entry.data_type) {
+            return Err(ReadWriteError::LengthMismatch);
+        }
+
+        entry.value = value;
+        entry.default = value;
+        Ok(())
+    }
+
+    /// Restores the default values of all entries within `indices`, e.g. on an NMT reset.
+    /// The contents of domains are kept.
+    pub fn restore_defaults(&mut self, indices: RangeInclusive<u16>) {
+        for entry in self.entries.values_mut() {
+            if indices.contains(&entry.index) {
+                entry.value = entry.default;
+            }
+        }
+    }
+
+    /// Sets the COB-IDs of the pre-defined connection set to the ones of `node_id`.
+    pub fn apply_node_id(&mut self, node_id: u8) {
+        for entry in self.entries.values_mut() {
+            if let Some(base) = entry.node_id_base {
+                entry.value = Value::Uint32(base + node_id as u32);
+            }
+        }
+    }
+
+    /// Stores the concise DCF of slave `node_id` in 0x1F22, downloaded by the NMT manager when booting the slave.
+    /// An empty domain means the slave is not configured.
+    pub fn add_concise_dcf(&mut self, node_id: u8, domain: &'static Domain) {
+        if self.get_entry(0x1F22, 0).is_err() {
+            self.add_entry(ObjectDictionaryEntry::new(0x1F22, 0, DataType::Unsigned8, AccessType::ReadOnly, Value::Uint8(127)));
+        }
+        self.add_entry(ObjectDictionaryEntry::new(0x1F22, node_id, DataType::Domain, AccessType::ReadWrite, Value::Domain(domain)));
+    }
+
+    fn missing_entry_error(&self, index: u16) -> ReadWriteError {
+        if self.entries.keys().any(|&(i, _)| i == index) {
+            ReadWriteError::SubindexDoesNotExist
+        } else {
+            ReadWriteError::ObjectDoesNotExist
+        }
     }
 
     fn new() -> Self {
@@ -115,7 +569,7 @@ impl<const N: usize> ObjectDictionary<N> {
     }
 
     #[allow(unused)]
-    pub fn new_canopen_301(_config: Config) -> Self {
+    pub fn new_canopen_301(config: Config) -> Self {
         let mut od = Self::new();
 
         // Example entries as per CANopen 301
@@ -126,6 +580,10 @@ impl<const N: usize> ObjectDictionary<N> {
             data_type: DataType::Unsigned32,
             access_type: AccessType::ReadOnly,
             value: Value::Uint32(0x00000000), // Replace with actual device type
+            default: Value::Uint32(0x00000000),
+            limits: None,
+            node_id_base: None,
+            pdo_mappable: false,
         });
 
         // Error Register (Index 0x1001)
@@ -135,6 +593,10 @@ impl<const N: usize> ObjectDictionary<N> {
             data_type: DataType::Unsigned8,
             access_type: AccessType::ReadOnly,
             value: Value::Uint8(0), // Replace with actual error register
+            default: Value::Uint8(0),
+            limits: None,
+            node_id_base: None,
+            pdo_mappable: true,
         });
 
         // Manufacturer Status Register (Index 0x1002) - optional
@@ -144,16 +606,19 @@ impl<const N: usize> ObjectDictionary<N> {
             data_type: DataType::Unsigned32,
             access_type: AccessType::ReadOnly,
             value: Value::Uint32(0), // Replace with actual status register
+            default: Value::Uint32(0),
+            limits: None,
+            node_id_base: None,
+            pdo_mappable: false,
         });
 
-        // Pre-defined error field (Index 0x1003) - Error history (optional)
-        od.add_entry(ObjectDictionaryEntry {
-            index: 0x1003,
-            subindex: 0,
-            data_type: DataType::Unsigned32,
-            access_type: AccessType::ReadOnly,
-            value: Value::Uint32(0), // Error history placeholder
-        });
+        // Pre-defined error field (Index 0x1003) - Error history, sub-index 0 holds the number of errors
+        if config.error_history_depth > 0 {
+            od.add_entry(ObjectDictionaryEntry::new(0x1003, 0, DataType::Unsigned8, AccessType::ReadWrite, Value::Uint8(0)));
+            for subindex in 1..=config.error_history_depth.min(254) {
+                od.add_entry(ObjectDictionaryEntry::new(0x1003, subindex, DataType::Unsigned32, AccessType::ReadOnly, Value::Uint32(0)));
+            }
+        }
 
         // COB-ID SYNC Message (Index 0x1005)
         od.add_entry(ObjectDictionaryEntry {
@@ -161,7 +626,11 @@ impl<const N: usize> ObjectDictionary<N> {
             subindex: 0,
             data_type: DataType::Unsigned32,
             access_type: AccessType::ReadWrite,
-            value: Value::Uint32(0x40000000), // Default COB-ID for SYNC
+            value: Value::Uint32(0x00000080), // Default COB-ID for SYNC, the node is SYNC consumer, setting bit 30 makes it SYNC producer
+            default: Value::Uint32(0x00000080),
+            limits: None,
+            node_id_base: None,
+            pdo_mappable: false,
         });
 
         // Communication cycle period (Index 0x1006)
@@ -171,8 +640,39 @@ impl<const N: usize> ObjectDictionary<N> {
             data_type: DataType::Unsigned32,
             access_type: AccessType::ReadWrite,
             value: Value::Uint32(0), // Optional, 0 = no sync period
+            default: Value::Uint32(0),
+            limits: None,
+            node_id_base: None,
+            pdo_mappable: false,
         });
 
+        // Synchronous window length in us (Index 0x1007), 0 = no window
+        od.add_entry(ObjectDictionaryEntry::new(0x1007, 0, DataType::Unsigned32, AccessType::ReadWrite, Value::Uint32(0)));
+
+        // Guard time in ms (Index 0x100C), 0 = no life guarding
+        od.add_entry(ObjectDictionaryEntry::new(0x100C, 0, DataType::Unsigned16, AccessType::ReadWrite, Value::Uint16(0)));
+
+        // Life time factor (Index 0x100D), life time = guard time * life time factor
+        od.add_entry(ObjectDictionaryEntry::new(0x100D, 0, DataType::Unsigned8, AccessType::ReadWrite, Value::Uint8(0)));
+
+        // COB-ID EMCY (Index 0x1014), bit 31 set = EMCY not valid
+        od.add_entry(
+            ObjectDictionaryEntry::new(0x1014, 0, DataType::Unsigned32, AccessType::ReadWrite, Value::Uint32(0x80))
+                .with_node_id_base(0x80),
+        );
+
+        // Inhibit time EMCY in 100 us (Index 0x1015)
+        od.add_entry(ObjectDictionaryEntry::new(0x1015, 0, DataType::Unsigned16, AccessType::ReadWrite, Value::Uint16(0)));
+
+        // Consumer heartbeat time (Index 0x1016), sub-index n holds node ID (bits 16-23) and time in ms (bits 0-15)
+        if config.heartbeat_consumer_count > 0 {
+            let count = config.heartbeat_consumer_count.min(127);
+            od.add_entry(ObjectDictionaryEntry::new(0x1016, 0, DataType::Unsigned8, AccessType::ReadOnly, Value::Uint8(count)));
+            for subindex in 1..=count {
+                od.add_entry(ObjectDictionaryEntry::new(0x1016, subindex, DataType::Unsigned32, AccessType::ReadWrite, Value::Uint32(0)));
+            }
+        }
+
         // Heartbeat Producer Time (Index 0x1017)
         od.add_entry(ObjectDictionaryEntry {
             index: 0x1017,
@@ -180,8 +680,131 @@ impl<const N: usize> ObjectDictionary<N> {
             data_type: DataType::Unsigned16,
             access_type: AccessType::ReadWrite,
             value: Value::Uint16(1000), // Default to 1000ms
+            default: Value::Uint16(1000),
+            limits: None,
+            node_id_base: None,
+            pdo_mappable: false,
         });
 
+        // Synchronous counter overflow value (Index 0x1019), 0 = SYNC without counter, otherwise 2 - 240
+        od.add_entry(
+            ObjectDictionaryEntry::new(0x1019, 0, DataType::Unsigned8, AccessType::ReadWrite, Value::Uint8(0))
+                .with_limits(Value::Uint8(0), Value::Uint8(240)),
+        );
+
+        // Emergency consumer (Index 0x1028), sub-index n holds the EMCY COB-ID of node n, bit 31 set = not consumed
+        if config.emcy_consumer_count > 0 {
+            let count = config.emcy_consumer_count.min(127);
+            od.add_entry(ObjectDictionaryEntry::new(0x1028, 0, DataType::Unsigned8, AccessType::ReadOnly, Value::Uint8(count)));
+            for node_id in 1..=count {
+                let cob_id = 0x8000_0000 | (0x80 + node_id as u32);
+                od.add_entry(ObjectDictionaryEntry::new(0x1028, node_id, DataType::Unsigned32, AccessType::ReadWrite, Value::Uint32(cob_id)));
+            }
+        }
+
+        // RPDO communication (Index 0x1400 + n) and mapping parameters (Index 0x1600 + n)
+        for n in 0..config.rpdo_count.min(512) {
+            od.add_rpdo_entries(n);
+        }
+
+        // TPDO communication (Index 0x1800 + n) and mapping parameters (Index 0x1A00 + n)
+        for n in 0..config.tpdo_count.min(512) {
+            od.add_tpdo_entries(n);
+        }
+
+        // NMT startup (Index 0x1F80), bit 0 set = NMT master
+        od.add_entry(ObjectDictionaryEntry::new(0x1F80, 0, DataType::Unsigned32, AccessType::ReadWrite, Value::Uint32(0)));
+
+        // NMT slave assignment (Index 0x1F81) and expected device type (0x1F84), vendor ID (0x1F85),
+        // product code (0x1F86), revision number (0x1F87) and serial number (0x1F88), sub-index n = node n
+        if config.boot_slave_count > 0 {
+            let count = config.boot_slave_count.min(127);
+            for index in [0x1F81, 0x1F84, 0x1F85, 0x1F86, 0x1F87, 0x1F88] {
+                od.add_entry(ObjectDictionaryEntry::new(index, 0, DataType::Unsigned8, AccessType::ReadOnly, Value::Uint8(count)));
+                for node_id in 1..=count {
+                    od.add_entry(ObjectDictionaryEntry::new(index, node_id, DataType::Unsigned32, AccessType::ReadWrite, Value::Uint32(0)));
+                }
+            }
+        }
+
         od
     }
-}
\ No newline at end of file
+
+    fn add_rpdo_entries(&mut self, n: u16) {
+        let communication = 0x1400 + n;
+        self.add_entry(ObjectDictionaryEntry::new(communication, 0, DataType::Unsigned8, AccessType::ReadOnly, Value::Uint8(5)));
+
+        // The first four RPDOs belong to the pre-defined connection set, the others are disabled
+        let cob_id = ObjectDictionaryEntry::new(communication, 1, DataType::Unsigned32, AccessType::ReadWrite, Value::Uint32(0x8000_0000));
+        self.add_entry(match n {
+            0..=3 => cob_id.with_node_id_base(0x200 + 0x100 * n as u32),
+            _ => cob_id,
+        });
+
+        // Transmission type, event-driven by default
+        self.add_entry(ObjectDictionaryEntry::new(communication, 2, DataType::Unsigned8, AccessType::ReadWrite, Value::Uint8(0xFF)));
+        // Event timer in ms, 0 = disabled
+        self.add_entry(ObjectDictionaryEntry::new(communication, 5, DataType::Unsigned16, AccessType::ReadWrite, Value::Uint16(0)));
+
+        let mapping = 0x1600 + n;
+        self.add_entry(ObjectDictionaryEntry::new(mapping, 0, DataType::Unsigned8, AccessType::ReadWrite, Value::Uint8(0)));
+        for subindex in 1..=8 {
+            self.add_entry(ObjectDictionaryEntry::new(mapping, subindex, DataType::Unsigned32, AccessType::ReadWrite, Value::Uint32(0)));
+        }
+    }
+
+    fn add_tpdo_entries(&mut self, n: u16) {
+        let communication = 0x1800 + n;
+        self.add_entry(ObjectDictionaryEntry::new(communication, 0, DataType::Unsigned8, AccessType::ReadOnly, Value::Uint8(6)));
+
+        // The first four TPDOs belong to the pre-defined connection set, the others are disabled
+        let cob_id = ObjectDictionaryEntry::new(communication, 1, DataType::Unsigned32, AccessType::ReadWrite, Value::Uint32(0x8000_0000));
+        self.add_entry(match n {
+            0..=3 => cob_id.with_node_id_base(0x180 + 0x100 * n as u32),
+            _ => cob_id,
+        });
+
+        // Transmission type, event-driven by default
+        self.add_entry(ObjectDictionaryEntry::new(communication, 2, DataType::Unsigned8, AccessType::ReadWrite, Value::Uint8(0xFF)));
+        // Inhibit time in 100 us, 0 = disabled
+        self.add_entry(ObjectDictionaryEntry::new(communication, 3, DataType::Unsigned16, AccessType::ReadWrite, Value::Uint16(0)));
+        // Event timer in ms, 0 = disabled
+        self.add_entry(ObjectDictionaryEntry::new(communication, 5, DataType::Unsigned16, AccessType::ReadWrite, Value::Uint16(0)));
+        // SYNC start value, 0 = SYNC counter not used
+        self.add_entry(ObjectDictionaryEntry::new(communication, 6, DataType::Unsigned8, AccessType::ReadWrite, Value::Uint8(0)));
+
+        let mapping = 0x1A00 + n;
+        self.add_entry(ObjectDictionaryEntry::new(mapping, 0, DataType::Unsigned8, AccessType::ReadWrite, Value::Uint8(0)));
+        for subindex in 1..=8 {
+            self.add_entry(ObjectDictionaryEntry::new(mapping, subindex, DataType::Unsigned32, AccessType::ReadWrite, Value::Uint32(0)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_history_keeps_additional_information() {
+        let mut od: ObjectDictionary<256> = ObjectDictionary::new_canopen_301(Config::default());
+        od.push_error_history(0x1234_8130);
+        od.push_error_history(0x0000_8250);
+
+        assert_eq!(od.get_value(0x1003, 0).unwrap().as_u32(), Some(2));
+        assert_eq!(od.get_value(0x1003, 1).unwrap().as_u32(), Some(0x0000_8250));
+        assert_eq!(od.get_value(0x1003, 2).unwrap().as_u32(), Some(0x1234_8130));
+    }
+
+    #[test]
+    fn set_default_survives_restore() {
+        let mut od: ObjectDictionary<256> = ObjectDictionary::new_canopen_301(Config { boot_slave_count: 2, ..Config::default() });
+        od.write(0x1F81, 1, Value::Uint32(0x09)).unwrap();
+        od.set_default(0x1F81, 2, Value::Uint32(0x01)).unwrap();
+        assert_eq!(od.set_default(0x1F81, 2, Value::Uint8(1)), Err(ReadWriteError::LengthMismatch));
+
+        od.restore_defaults(0x1000..=0x1FFF);
+        assert_eq!(od.get_value(0x1F81, 1).unwrap().as_u32(), Some(0));
+        assert_eq!(od.get_value(0x1F81, 2).unwrap().as_u32(), Some(0x01));
+    }
+}
@@ -0,0 +1,457 @@
+use embassy_time::Instant;
+
+use crate::object_dictionary::{DataType, ObjectDictionary, ReadWriteError, Value};
+
+pub(crate) const RPDO_COMMUNICATION: u16 = 0x1400;
+pub(crate) const RPDO_MAPPING: u16 = 0x1600;
+pub(crate) const TPDO_COMMUNICATION: u16 = 0x1800;
+pub(crate) const TPDO_MAPPING: u16 = 0x1A00;
+
+/// Maximum number of PDOs in each direction.
+pub(crate) const MAX_PDOS: u16 = 512;
+
+/// Number of RPDOs, starting with the first, whose deadline is monitored and whose synchronous data is latched.
+pub(crate) const TRACKED_RPDOS: usize = 32;
+
+/// Reception state of an RPDO kept by the node.
+#[derive(Copy, Clone, Default)]
+pub(crate) struct RpdoState {
+    /// Time the event timer elapses, restarted with every reception.
+    pub(crate) deadline: Option<Instant>,
+    /// Data of a synchronous RPDO, written to the object dictionary with the next SYNC.
+    pub(crate) latched: Option<([u8; 8], usize)>,
+    /// The event timer elapsed without reception.
+    pub(crate) timed_out: bool,
+}
+
+/// Synchronous window error (EMCY 0x8100) kept by the node.
+#[derive(Copy, Clone, Default)]
+pub(crate) struct SyncWindowState {
+    /// Synchronous PDOs were discarded and none was sent within its window since.
+    pub(crate) error: bool,
+}
+
+impl SyncWindowState {
+    /// Synchronous PDOs were discarded, returns true if the error has to be raised.
+    pub(crate) fn expired(&mut self) -> bool {
+        !core::mem::replace(&mut self.error, true)
+    }
+
+    /// A synchronous PDO was sent within its window, returns true if the error has to be cleared.
+    pub(crate) fn sent(&mut self) -> bool {
+        core::mem::take(&mut self.error)
+    }
+}
+
+/// COB-ID used by PDO, sub-index 1 of a communication parameter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct PdoCobId(pub(crate) u32);
+
+impl PdoCobId {
+    /// Bit 31 cleared: the PDO exists and is valid.
+    pub(crate) fn is_valid(&self) -> bool {
+        self.0 & 0x8000_0000 == 0
+    }
+
+    /// Bit 30 cleared: remote transmission requests are allowed.
+    pub(crate) fn rtr_allowed(&self) -> bool {
+        self.0 & 0x4000_0000 == 0
+    }
+
+    /// Bit 29 set: the PDO uses a 29-bit CAN-ID.
+    pub(crate) fn is_extended(&self) -> bool {
+        self.0 & 0x2000_0000 != 0
+    }
+
+    pub(crate) fn can_id(&self) -> u32 {
+        if self.is_extended() {
+            self.0 & 0x1FFF_FFFF
+        } else {
+            self.0 & 0x7FF
+        }
+    }
+}
+
+/// An object mapped into a PDO, sub-index 1..=64 of a mapping parameter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct PdoMapping {
+    pub(crate) index: u16,
+    pub(crate) subindex: u8,
+    pub(crate) bits: u8,
+}
+
+impl PdoMapping {
+    /// Dummy entries (data type indices below 0x1000) only occupy space in the PDO.
+    pub(crate) fn is_dummy(&self) -> bool {
+        self.index < 0x1000
+    }
+}
+
+impl From<u32> for PdoMapping {
+    fn from(value: u32) -> Self {
+        Self {
+            index: (value >> 16) as u16,
+            subindex: (value >> 8) as u8,
+            bits: value as u8,
+        }
+    }
+}
+
+/// Reads an unsigned entry of a PDO parameter.
+pub(crate) fn parameter<const N: usize>(od: &ObjectDictionary<N>, index: u16, subindex: u8) -> Result<u32, ReadWriteError> {
+    od.get_value(index, subindex)?.as_u32().ok_or(ReadWriteError::LengthMismatch)
+}
+
+/// Finds the valid PDO with the 11-bit `cob_id` among the communication parameters
+/// starting at `communication_index`. Returns its number (0-based) and COB-ID entry.
+pub(crate) fn find_pdo<const N: usize>(od: &ObjectDictionary<N>, communication_index: u16, cob_id: u16) -> Option<(u16, PdoCobId)> {
+    (0..MAX_PDOS)
+        .map_while(|n| parameter(od, communication_index + n, 1).ok().map(|value| (n, PdoCobId(value))))
+        .find(|(_, pdo_cob_id)| pdo_cob_id.is_valid() && !pdo_cob_id.is_extended() && pdo_cob_id.can_id() == cob_id as u32)
+}
+
+/// Transmission type, sub-index 2 of a TPDO communication parameter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum TransmissionType {
+    /// 0: sent with the first SYNC after an event.
+    SynchronousAcyclic,
+    /// 1..=240: sent with every n-th SYNC.
+    SynchronousCyclic(u8),
+    /// 252: sampled with SYNC, sent on RTR.
+    SynchronousRtr,
+    /// 253: sampled and sent on RTR.
+    EventRtr,
+    /// 254, 255: sent on application events.
+    Event,
+}
+
+impl TransmissionType {
+    /// Whether an RPDO of this type is applied with the next SYNC.
+    pub(crate) fn is_synchronous(&self) -> bool {
+        matches!(self, Self::SynchronousAcyclic | Self::SynchronousCyclic(_))
+    }
+}
+
+impl TransmissionType {
+    /// Decodes a transmission type, `None` for reserved values.
+    pub(crate) fn new(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::SynchronousAcyclic),
+            1..=240 => Some(Self::SynchronousCyclic(value)),
+            252 => Some(Self::SynchronousRtr),
+            253 => Some(Self::EventRtr),
+            254 | 255 => Some(Self::Event),
+            _ => None,
+        }
+    }
+}
+
+/// Builds the data of a PDO from the objects mapped by the mapping parameter at `mapping_index`.
+/// Returns the data and its length in bytes.
+pub(crate) fn pack<const N: usize>(od: &ObjectDictionary<N>, mapping_index: u16) -> Result<([u8; 8], usize), ReadWriteError> {
+    let count = parameter(od, mapping_index, 0)? as u8;
+
+    let mut payload = 0u64;
+    let mut offset = 0u32;
+    for subindex in 1..=count {
+        let mapping = PdoMapping::from(parameter(od, mapping_index, subindex)?);
+        if offset + mapping.bits as u32 > 64 {
+            return Err(ReadWriteError::PdoLengthExceeded);
+        }
+
+        if !mapping.is_dummy() {
+            let entry = od.get_entry(mapping.index, mapping.subindex)?;
+            if !fits(entry.data_type(), mapping.bits) {
+                return Err(ReadWriteError::LengthMismatch);
+            }
+
+            let mut bytes = [0u8; 8];
+            entry.read()?.read_bytes(0, &mut bytes);
+            payload |= (u64::from_le_bytes(bytes) & mask(mapping.bits)) << offset;
+        }
+        offset += mapping.bits as u32;
+    }
+
+    Ok((payload.to_le_bytes(), offset.div_ceil(8) as usize))
+}
+
+/// Checks a write to a PDO communication or mapping parameter against the current configuration.
+///
+/// Mappings can only be changed following the CiA 301 procedure: invalidate the PDO (COB-ID bit 31),
+/// write 0 to sub-index 0, write the mapping entries, write their number to sub-index 0 and
+/// validate the PDO again.
+pub(crate) fn check_parameter_write<const N: usize>(od: &ObjectDictionary<N>, index: u16, subindex: u8, value: Value) -> Result<(), ReadWriteError> {
+    let (communication_index, receive) = match index {
+        0x1400..=0x15FF => (index, true),
+        0x1600..=0x17FF => (index - 0x200, true),
+        0x1800..=0x19FF => (index, false),
+        0x1A00..=0x1BFF => (index - 0x200, false),
+        _ => return Ok(()),
+    };
+    let Some(value) = value.as_u32() else {
+        // Left to the type check of the entry
+        return Ok(());
+    };
+
+    let current_cob_id = PdoCobId(parameter(od, communication_index, 1)?);
+
+    if index == communication_index {
+        if subindex != 1 {
+            return Ok(());
+        }
+
+        // Only the valid bit may change while the PDO exists
+        let cob_id = PdoCobId(value);
+        if cob_id.is_valid() && (cob_id.is_extended() || (current_cob_id.is_valid() && cob_id.can_id() != current_cob_id.can_id())) {
+            return Err(ReadWriteError::ValueRangeExceeded);
+        }
+        return Ok(());
+    }
+
+    if current_cob_id.is_valid() {
+        return Err(ReadWriteError::WrongDeviceState);
+    }
+
+    if subindex == 0 {
+        if value > 64 {
+            return Err(ReadWriteError::PdoLengthExceeded);
+        }
+
+        let mut mapped_bits = 0;
+        for subindex in 1..=value as u8 {
+            let mapping = od.get_value(index, subindex).ok().and_then(|v| v.as_u32()).ok_or(ReadWriteError::PdoLengthExceeded)?;
+            let mapping = PdoMapping::from(mapping);
+            check_mapping(od, mapping, receive)?;
+            mapped_bits += mapping.bits as u32;
+        }
+
+        if mapped_bits > 64 {
+            return Err(ReadWriteError::PdoLengthExceeded);
+        }
+        Ok(())
+    } else if parameter(od, index, 0)? != 0 {
+        // Entries can only be changed while the mapping is disabled
+        Err(ReadWriteError::WrongDeviceState)
+    } else if value == 0 {
+        Ok(())
+    } else {
+        check_mapping(od, PdoMapping::from(value), receive)
+    }
+}
+
+// Checks that the mapped object exists and can be mapped with the given length
+fn check_mapping<const N: usize>(od: &ObjectDictionary<N>, mapping: PdoMapping, receive: bool) -> Result<(), ReadWriteError> {
+    if mapping.is_dummy() {
+        return match dummy_type(mapping.index) {
+            Some(data_type) if fits(data_type, mapping.bits) => Ok(()),
+            Some(_) => Err(ReadWriteError::NotMappable),
+            None => Err(ReadWriteError::ObjectDoesNotExist),
+        };
+    }
+
+    let entry = od.get_entry(mapping.index, mapping.subindex)?;
+    let accessible = if receive { entry.is_writable() } else { entry.is_readable() };
+    if !entry.is_pdo_mappable() || !accessible || !fits(entry.data_type(), mapping.bits) {
+        return Err(ReadWriteError::NotMappable);
+    }
+    Ok(())
+}
+
+// Data types that can be used as dummy entries
+fn dummy_type(index: u16) -> Option<DataType> {
+    match index {
+        0x0001 => Some(DataType::Boolean),
+        0x0002 => Some(DataType::Integer8),
+        0x0003 => Some(DataType::Integer16),
+        0x0004 => Some(DataType::Integer32),
+        0x0005 => Some(DataType::Unsigned8),
+        0x0006 => Some(DataType::Unsigned16),
+        0x0007 => Some(DataType::Unsigned32),
+        _ => None,
+    }
+}
+
+/// Writes the data of a received PDO into the objects mapped by the mapping parameter at `mapping_index`.
+///
+/// Nothing is written if `data` is shorter than the mapped objects.
+pub(crate) fn unpack<const N: usize>(od: &mut ObjectDictionary<N>, mapping_index: u16, data: &[u8]) -> Result<(), ReadWriteError> {
+    let count = parameter(od, mapping_index, 0)? as u8;
+
+    let mut mapped_bits = 0;
+    for subindex in 1..=count {
+        mapped_bits += PdoMapping::from(parameter(od, mapping_index, subindex)?).bits as usize;
+    }
+    if mapped_bits > data.len().min(8) * 8 {
+        return Err(ReadWriteError::LengthTooLow);
+    }
+
+    let mut raw = [0u8; 8];
+    raw[..data.len().min(8)].copy_from_slice(&data[..data.len().min(8)]);
+    let payload = u64::from_le_bytes(raw);
+
+    let mut offset = 0;
+    for subindex in 1..=count {
+        let mapping = PdoMapping::from(parameter(od, mapping_index, subindex)?);
+        let bits = payload.checked_shr(offset).unwrap_or(0) & mask(mapping.bits);
+        offset += mapping.bits as u32;
+
+        if mapping.is_dummy() {
+            continue;
+        }
+
+        let entry = od.get_entry_mut(mapping.index, mapping.subindex)?;
+        let value = value_from_bits(entry.data_type(), bits, mapping.bits)?;
+        entry.write(value)?;
+    }
+
+    Ok(())
+}
+
+fn mask(bits: u8) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+/// Whether an object of `data_type` can be mapped with a length of `bits`.
+pub(crate) fn fits(data_type: DataType, bits: u8) -> bool {
+    match data_type {
+        DataType::Boolean => bits == 1 || bits == 8,
+        _ => data_type.size().is_some_and(|size| size * 8 == bits as usize),
+    }
+}
+
+fn value_from_bits(data_type: DataType, bits: u64, len: u8) -> Result<Value, ReadWriteError> {
+    if !fits(data_type, len) {
+        return Err(ReadWriteError::LengthMismatch);
+    }
+
+    match data_type {
+        DataType::Boolean => Ok(Value::Bool(bits != 0)),
+        _ => Value::from_le_bytes(data_type, &bits.to_le_bytes()[..len as usize / 8]).ok_or(ReadWriteError::LengthMismatch),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object_dictionary::{AccessType, Config, ObjectDictionaryEntry};
+
+    // Node 1 with RPDO 1 / TPDO 1 valid on 0x201 / 0x181 and application objects to map:
+    // 0x2000 (u16, mappable), 0x2001 (u32, not mappable) and 0x2002 (u8, read only, mappable)
+    fn od() -> ObjectDictionary<256> {
+        let mut od = ObjectDictionary::new_canopen_301(Config::default());
+        od.apply_node_id(1);
+        od.add_entry(ObjectDictionaryEntry::new(0x2000, 0, DataType::Unsigned16, AccessType::ReadWrite, Value::Uint16(0)).with_pdo_mapping());
+        od.add_entry(ObjectDictionaryEntry::new(0x2001, 0, DataType::Unsigned32, AccessType::ReadWrite, Value::Uint32(0)));
+        od.add_entry(ObjectDictionaryEntry::new(0x2002, 0, DataType::Unsigned8, AccessType::ReadOnly, Value::Uint8(0)).with_pdo_mapping());
+        od
+    }
+
+    // Invalidates PDO `communication_index` and disables its mapping
+    fn disable_mapping(od: &mut ObjectDictionary<256>, communication_index: u16) {
+        od.write(communication_index, 1, Value::Uint32(0x8000_0000)).unwrap();
+        od.write(communication_index + 0x200, 0, Value::Uint8(0)).unwrap();
+    }
+
+    fn pdo_cob_id(od: &ObjectDictionary<256>, communication_index: u16) -> PdoCobId {
+        PdoCobId(parameter(od, communication_index, 1).unwrap())
+    }
+
+    #[test]
+    fn cob_id_can_only_change_while_invalid() {
+        let mut od = od();
+        assert_eq!(od.write(0x1400, 1, Value::Uint32(0x202)), Err(ReadWriteError::ValueRangeExceeded));
+        assert_eq!(od.write(0x1400, 1, Value::Uint32(0x201)), Ok(()));
+
+        assert_eq!(od.write(0x1400, 1, Value::Uint32(0x8000_0201)), Ok(()));
+        assert_eq!(od.write(0x1400, 1, Value::Uint32(0x202)), Ok(()));
+        assert_eq!(pdo_cob_id(&od, 0x1400), PdoCobId(0x202));
+
+        // 29-bit CAN-IDs are not supported
+        od.write(0x1400, 1, Value::Uint32(0x8000_0202)).unwrap();
+        assert_eq!(od.write(0x1400, 1, Value::Uint32(0x2000_0202)), Err(ReadWriteError::ValueRangeExceeded));
+    }
+
+    #[test]
+    fn mapping_requires_invalid_pdo_and_disabled_mapping() {
+        let mut od = od();
+        assert_eq!(od.write(0x1600, 0, Value::Uint8(0)), Err(ReadWriteError::WrongDeviceState));
+        assert_eq!(od.write(0x1600, 1, Value::Uint32(0x2000_0010)), Err(ReadWriteError::WrongDeviceState));
+
+        od.write(0x1400, 1, Value::Uint32(0x8000_0201)).unwrap();
+        assert_eq!(od.write(0x1600, 1, Value::Uint32(0x2000_0010)), Ok(()));
+        assert_eq!(od.write(0x1600, 0, Value::Uint8(1)), Ok(()));
+
+        // Entries cannot be changed until sub-index 0 is 0 again
+        assert_eq!(od.write(0x1600, 1, Value::Uint32(0x2002_0008)), Err(ReadWriteError::WrongDeviceState));
+        od.write(0x1600, 0, Value::Uint8(0)).unwrap();
+        assert_eq!(od.write(0x1600, 1, Value::Uint32(0)), Ok(()));
+    }
+
+    #[test]
+    fn mapping_rejects_objects_that_cannot_be_mapped() {
+        let mut od = od();
+        disable_mapping(&mut od, 0x1400);
+        disable_mapping(&mut od, 0x1800);
+
+        assert_eq!(od.write(0x1600, 1, Value::Uint32(0x2001_0020)), Err(ReadWriteError::NotMappable));
+        assert_eq!(od.write(0x1600, 1, Value::Uint32(0x3000_0010)), Err(ReadWriteError::ObjectDoesNotExist));
+        assert_eq!(od.write(0x1600, 1, Value::Uint32(0x2000_0110)), Err(ReadWriteError::SubindexDoesNotExist));
+        // The length has to match the data type
+        assert_eq!(od.write(0x1600, 1, Value::Uint32(0x2000_0008)), Err(ReadWriteError::NotMappable));
+
+        // Read only objects can only be sent
+        assert_eq!(od.write(0x1600, 1, Value::Uint32(0x2002_0008)), Err(ReadWriteError::NotMappable));
+        assert_eq!(od.write(0x1A00, 1, Value::Uint32(0x2002_0008)), Ok(()));
+    }
+
+    #[test]
+    fn mapping_rejects_more_than_64_bits() {
+        let mut od = od();
+        disable_mapping(&mut od, 0x1400);
+
+        for subindex in 1..=5 {
+            od.write(0x1600, subindex, Value::Uint32(0x2000_0010)).unwrap();
+        }
+        assert_eq!(od.write(0x1600, 0, Value::Uint8(4)), Ok(()));
+        od.write(0x1600, 0, Value::Uint8(0)).unwrap();
+        assert_eq!(od.write(0x1600, 0, Value::Uint8(5)), Err(ReadWriteError::PdoLengthExceeded));
+        assert_eq!(od.write(0x1600, 0, Value::Uint8(65)), Err(ReadWriteError::PdoLengthExceeded));
+        assert_eq!(parameter(&od, 0x1600, 0), Ok(0));
+    }
+
+    #[test]
+    fn mapping_accepts_dummy_entries() {
+        let mut od = od();
+        disable_mapping(&mut od, 0x1400);
+
+        assert_eq!(od.write(0x1600, 1, Value::Uint32(0x0005_0008)), Ok(()));
+        assert_eq!(od.write(0x1600, 2, Value::Uint32(0x0001_0001)), Ok(()));
+        assert_eq!(od.write(0x1600, 0, Value::Uint8(2)), Ok(()));
+
+        od.write(0x1600, 0, Value::Uint8(0)).unwrap();
+        assert_eq!(od.write(0x1600, 1, Value::Uint32(0x0005_0010)), Err(ReadWriteError::NotMappable));
+        // 0x0008 (REAL32) cannot be used as dummy entry
+        assert_eq!(od.write(0x1600, 1, Value::Uint32(0x0008_0020)), Err(ReadWriteError::ObjectDoesNotExist));
+    }
+
+    #[test]
+    fn sync_window_error_raised_once_and_cleared() {
+        let mut sync_window = SyncWindowState::default();
+        assert!(!sync_window.sent());
+
+        assert!(sync_window.expired());
+        // Further expired windows do not raise the error again
+        assert!(!sync_window.expired());
+
+        assert!(sync_window.sent());
+        assert!(!sync_window.sent());
+        assert!(sync_window.expired());
+    }
+}
@@ -0,0 +1,1049 @@
+use embassy_time::{Duration, Instant};
+
+use crate::object_dictionary::{DataType, Domain, ObjectDictionary, ObjectDictionaryEntry, ReadWriteError, Value};
+
+// Client command specifiers (bits 7..5 of the first byte of a request)
+pub(crate) const CCS_DOWNLOAD_SEGMENT: u8 = 0;
+pub(crate) const CCS_INITIATE_DOWNLOAD: u8 = 1;
+pub(crate) const CCS_INITIATE_UPLOAD: u8 = 2;
+pub(crate) const CCS_UPLOAD_SEGMENT: u8 = 3;
+pub(crate) const CCS_ABORT: u8 = 4;
+pub(crate) const CCS_BLOCK_UPLOAD: u8 = 5;
+pub(crate) const CCS_BLOCK_DOWNLOAD: u8 = 6;
+
+// Server command specifiers (bits 7..5 of the first byte of a response)
+pub(crate) const SCS_UPLOAD_SEGMENT: u8 = 0;
+pub(crate) const SCS_DOWNLOAD_SEGMENT: u8 = 1;
+pub(crate) const SCS_INITIATE_UPLOAD: u8 = 2;
+pub(crate) const SCS_INITIATE_DOWNLOAD: u8 = 3;
+pub(crate) const SCS_ABORT: u8 = 4;
+pub(crate) const SCS_BLOCK_DOWNLOAD: u8 = 5;
+pub(crate) const SCS_BLOCK_UPLOAD: u8 = 6;
+
+// Client subcommands of a block upload (bits 1..0)
+const CS_BLOCK_UPLOAD_INITIATE: u8 = 0;
+const CS_BLOCK_UPLOAD_END: u8 = 1;
+const CS_BLOCK_UPLOAD_ACK: u8 = 2;
+const CS_BLOCK_UPLOAD_START: u8 = 3;
+
+// Client subcommands of a block download (bit 0)
+const CS_BLOCK_DOWNLOAD_INITIATE: u8 = 0;
+const CS_BLOCK_DOWNLOAD_END: u8 = 1;
+
+// Server subcommands of block transfers (bits 1..0)
+const SS_BLOCK_INITIATE: u8 = 0;
+const SS_BLOCK_END: u8 = 1;
+const SS_BLOCK_ACK: u8 = 2;
+
+/// Number of segments per block the server accepts in a block download.
+const BLOCK_SIZE: u8 = 127;
+
+/// Time the server waits for the next request of a segmented transfer before aborting it.
+pub const DEFAULT_SDO_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// SDO abort codes as defined in CiA 301.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AbortCode {
+    /// 0x05030000: Toggle bit not alternated.
+    ToggleBitNotAlternated,
+    /// 0x05040000: SDO protocol timed out.
+    ProtocolTimedOut,
+    /// 0x05040001: Client/server command specifier not valid or unknown.
+    CommandSpecifierInvalid,
+    /// 0x05040002: Invalid block size (block mode only).
+    InvalidBlockSize,
+    /// 0x05040003: Invalid sequence number (block mode only).
+    InvalidSequenceNumber,
+    /// 0x05040004: CRC error (block mode only).
+    CrcError,
+    /// 0x05040005: Out of memory.
+    OutOfMemory,
+    /// 0x06010000: Unsupported access to an object.
+    UnsupportedAccess,
+    /// 0x06010001: Attempt to read a write only object.
+    WriteOnly,
+    /// 0x06010002: Attempt to write a read only object.
+    ReadOnly,
+    /// 0x06020000: Object does not exist in the object dictionary.
+    ObjectDoesNotExist,
+    /// 0x06040041: Object cannot be mapped to the PDO.
+    NotMappable,
+    /// 0x06040042: The number and length of the objects to be mapped would exceed PDO length.
+    PdoLengthExceeded,
+    /// 0x06040043: General parameter incompatibility reason.
+    ParameterIncompatibility,
+    /// 0x06040047: General internal incompatibility in the device.
+    InternalIncompatibility,
+    /// 0x06060000: Access failed due to a hardware error.
+    HardwareError,
+    /// 0x06070010: Data type does not match, length of service parameter does not match.
+    LengthMismatch,
+    /// 0x06070012: Data type does not match, length of service parameter too high.
+    LengthTooHigh,
+    /// 0x06070013: Data type does not match, length of service parameter too low.
+    LengthTooLow,
+    /// 0x06090011: Sub-index does not exist.
+    SubindexDoesNotExist,
+    /// 0x06090030: Invalid value for parameter (download only).
+    ValueRangeExceeded,
+    /// 0x06090031: Value of parameter written too high (download only).
+    ValueTooHigh,
+    /// 0x06090032: Value of parameter written too low (download only).
+    ValueTooLow,
+    /// 0x06090036: Maximum value is less than minimum value.
+    MaxLessThanMin,
+    /// 0x060A0023: Resource not available: SDO connection.
+    ResourceNotAvailable,
+    /// 0x08000000: General error.
+    GeneralError,
+    /// 0x08000020: Data cannot be transferred or stored to the application.
+    DataTransfer,
+    /// 0x08000021: Data cannot be transferred or stored to the application because of local control.
+    LocalControl,
+    /// 0x08000022: Data cannot be transferred or stored to the application because of the present device state.
+    WrongDeviceState,
+    /// 0x08000023: Object dictionary dynamic generation fails or no object dictionary is present.
+    NoObjectDictionary,
+    /// 0x08000024: No data available.
+    NoDataAvailable,
+    /// Any other, manufacturer specific, abort code received from a remote node.
+    Other(u32),
+}
+
+impl AbortCode {
+    const KNOWN: [AbortCode; 31] = [
+        AbortCode::ToggleBitNotAlternated,
+        AbortCode::ProtocolTimedOut,
+        AbortCode::CommandSpecifierInvalid,
+        AbortCode::InvalidBlockSize,
+        AbortCode::InvalidSequenceNumber,
+        AbortCode::CrcError,
+        AbortCode::OutOfMemory,
+        AbortCode::UnsupportedAccess,
+        AbortCode::WriteOnly,
+        AbortCode::ReadOnly,
+        AbortCode::ObjectDoesNotExist,
+        AbortCode::NotMappable,
+        AbortCode::PdoLengthExceeded,
+        AbortCode::ParameterIncompatibility,
+        AbortCode::InternalIncompatibility,
+        AbortCode::HardwareError,
+        AbortCode::LengthMismatch,
+        AbortCode::LengthTooHigh,
+        AbortCode::LengthTooLow,
+        AbortCode::SubindexDoesNotExist,
+        AbortCode::ValueRangeExceeded,
+        AbortCode::ValueTooHigh,
+        AbortCode::ValueTooLow,
+        AbortCode::MaxLessThanMin,
+        AbortCode::ResourceNotAvailable,
+        AbortCode::GeneralError,
+        AbortCode::DataTransfer,
+        AbortCode::LocalControl,
+        AbortCode::WrongDeviceState,
+        AbortCode::NoObjectDictionary,
+        AbortCode::NoDataAvailable,
+    ];
+}
+
+impl From<u32> for AbortCode {
+    fn from(code: u32) -> Self {
+        AbortCode::KNOWN
+            .into_iter()
+            .find(|known| u32::from(*known) == code)
+            .unwrap_or(AbortCode::Other(code))
+    }
+}
+
+impl From<AbortCode> for u32 {
+    fn from(code: AbortCode) -> Self {
+        match code {
+            AbortCode::ToggleBitNotAlternated => 0x0503_0000,
+            AbortCode::ProtocolTimedOut => 0x0504_0000,
+            AbortCode::CommandSpecifierInvalid => 0x0504_0001,
+            AbortCode::InvalidBlockSize => 0x0504_0002,
+            AbortCode::InvalidSequenceNumber => 0x0504_0003,
+            AbortCode::CrcError => 0x0504_0004,
+            AbortCode::OutOfMemory => 0x0504_0005,
+            AbortCode::UnsupportedAccess => 0x0601_0000,
+            AbortCode::WriteOnly => 0x0601_0001,
+            AbortCode::ReadOnly => 0x0601_0002,
+            AbortCode::ObjectDoesNotExist => 0x0602_0000,
+            AbortCode::NotMappable => 0x0604_0041,
+            AbortCode::PdoLengthExceeded => 0x0604_0042,
+            AbortCode::ParameterIncompatibility => 0x0604_0043,
+            AbortCode::InternalIncompatibility => 0x0604_0047,
+            AbortCode::HardwareError => 0x0606_0000,
+            AbortCode::LengthMismatch => 0x0607_0010,
+            AbortCode::LengthTooHigh => 0x0607_0012,
+            AbortCode::LengthTooLow => 0x0607_0013,
+            AbortCode::SubindexDoesNotExist => 0x0609_0011,
+            AbortCode::ValueRangeExceeded => 0x0609_0030,
+            AbortCode::ValueTooHigh => 0x0609_0031,
+            AbortCode::ValueTooLow => 0x0609_0032,
+            AbortCode::MaxLessThanMin => 0x0609_0036,
+            AbortCode::ResourceNotAvailable => 0x060A_0023,
+            AbortCode::GeneralError => 0x0800_0000,
+            AbortCode::DataTransfer => 0x0800_0020,
+            AbortCode::LocalControl => 0x0800_0021,
+            AbortCode::WrongDeviceState => 0x0800_0022,
+            AbortCode::NoObjectDictionary => 0x0800_0023,
+            AbortCode::NoDataAvailable => 0x0800_0024,
+            AbortCode::Other(code) => code,
+        }
+    }
+}
+
+impl From<ReadWriteError> for AbortCode {
+    fn from(error: ReadWriteError) -> Self {
+        match error {
+            ReadWriteError::WriteOnly => AbortCode::WriteOnly,
+            ReadWriteError::ReadOnly => AbortCode::ReadOnly,
+            ReadWriteError::UnsupportedAccess => AbortCode::UnsupportedAccess,
+            ReadWriteError::ObjectDoesNotExist => AbortCode::ObjectDoesNotExist,
+            ReadWriteError::SubindexDoesNotExist => AbortCode::SubindexDoesNotExist,
+            ReadWriteError::NotMappable => AbortCode::NotMappable,
+            ReadWriteError::PdoLengthExceeded => AbortCode::PdoLengthExceeded,
+            ReadWriteError::ParameterIncompatibility => AbortCode::ParameterIncompatibility,
+            ReadWriteError::InternalIncompatibility => AbortCode::InternalIncompatibility,
+            ReadWriteError::HardwareError => AbortCode::HardwareError,
+            ReadWriteError::LengthMismatch => AbortCode::LengthMismatch,
+            ReadWriteError::LengthTooHigh => AbortCode::LengthTooHigh,
+            ReadWriteError::LengthTooLow => AbortCode::LengthTooLow,
+            ReadWriteError::ValueRangeExceeded => AbortCode::ValueRangeExceeded,
+            ReadWriteError::ValueTooHigh => AbortCode::ValueTooHigh,
+            ReadWriteError::ValueTooLow => AbortCode::ValueTooLow,
+            ReadWriteError::MaxLessThanMin => AbortCode::MaxLessThanMin,
+            ReadWriteError::LocalControl => AbortCode::LocalControl,
+            ReadWriteError::WrongDeviceState => AbortCode::WrongDeviceState,
+            ReadWriteError::NoDataAvailable => AbortCode::NoDataAvailable,
+            ReadWriteError::OutOfMemory => AbortCode::OutOfMemory,
+        }
+    }
+}
+
+// Where the data of a download ends up
+enum DownloadTarget {
+    // Streamed directly into the domain of the entry
+    Domain(&'static Domain),
+    // Collected and written as a whole once the transfer is complete
+    Fixed { data_type: DataType, buffer: [u8; 4] },
+}
+
+impl DownloadTarget {
+    fn for_entry(entry: &ObjectDictionaryEntry) -> Result<Self, AbortCode> {
+        if !entry.is_writable() {
+            return Err(AbortCode::ReadOnly);
+        }
+
+        match (entry.value, entry.data_type().size()) {
+            (Value::Domain(domain), _) => Ok(DownloadTarget::Domain(domain)),
+            (_, Some(_)) => Ok(DownloadTarget::Fixed { data_type: entry.data_type(), buffer: [0; 4] }),
+            // Variable length data without a domain to store it in
+            (_, None) => Err(AbortCode::UnsupportedAccess),
+        }
+    }
+
+    fn check_size(&self, size: usize) -> Result<(), AbortCode> {
+        let max = match self {
+            DownloadTarget::Domain(domain) => domain.capacity(),
+            DownloadTarget::Fixed { data_type, .. } => data_type.size().unwrap_or(0),
+        };
+
+        if size > max {
+            Err(AbortCode::LengthTooHigh)
+        } else if matches!(self, DownloadTarget::Fixed { .. }) && size < max {
+            Err(AbortCode::LengthTooLow)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Called once the download is accepted. A download always replaces the whole content of a domain,
+    // it is written in place, so a failed download leaves the bytes received until then instead of the old content.
+    fn start(&self) {
+        if let DownloadTarget::Domain(domain) = self {
+            let _ = domain.set_len(0);
+        }
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), AbortCode> {
+        match self {
+            DownloadTarget::Domain(domain) => Ok(domain.write(offset, data)?),
+            DownloadTarget::Fixed { buffer, .. } => {
+                let dest = buffer.get_mut(offset..offset + data.len()).ok_or(AbortCode::LengthTooHigh)?;
+                dest.copy_from_slice(data);
+                Ok(())
+            }
+        }
+    }
+
+    fn finish<const N: usize>(&self, od: &mut ObjectDictionary<N>, index: u16, subindex: u8, len: usize) -> Result<(), AbortCode> {
+        match self {
+            DownloadTarget::Domain(domain) => Ok(domain.set_len(len)?),
+            DownloadTarget::Fixed { data_type, buffer } => {
+                let value = Value::from_le_bytes(*data_type, &buffer[..len]).ok_or(AbortCode::LengthMismatch)?;
+                Ok(od.write(index, subindex, value)?)
+            }
+        }
+    }
+}
+
+enum Transfer {
+    Idle,
+    Download {
+        index: u16,
+        subindex: u8,
+        target: DownloadTarget,
+        size: Option<usize>,
+        offset: usize,
+        toggle: bool,
+    },
+    Upload {
+        index: u16,
+        subindex: u8,
+        size: usize,
+        offset: usize,
+        toggle: bool,
+    },
+    BlockDownload {
+        index: u16,
+        subindex: u8,
+        target: DownloadTarget,
+        size: Option<usize>,
+        // Bytes written to the target so far
+        offset: usize,
+        // Running CRC if both sides support it
+        crc: Option<u16>,
+        // Last segment received in sequence within the current block
+        seqno: u8,
+        // The last segment in sequence is only written once it is known not to be padded,
+        // i.e. when the next segment or the end request arrives
+        pending: Option<[u8; 7]>,
+        // The last segment of the transfer was received, waiting for the end request
+        complete: bool,
+    },
+    BlockUpload {
+        index: u16,
+        subindex: u8,
+        size: usize,
+        crc: bool,
+        blksize: u8,
+        // Offset of the first segment of the current block
+        block_start: usize,
+        // Offset of the next segment to send
+        offset: usize,
+        // Last segment sent within the current block
+        seqno: u8,
+        phase: BlockUploadPhase,
+    },
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum BlockUploadPhase {
+    Initiated,
+    Sending,
+    WaitingForAck,
+    WaitingForEnd,
+}
+
+/// SDO server handling expedited, segmented and block transfers, one at a time.
+pub(crate) struct SdoServer {
+    transfer: Transfer,
+    timeout: Duration,
+    deadline: Instant,
+}
+
+impl SdoServer {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self {
+            transfer: Transfer::Idle,
+            timeout,
+            deadline: Instant::MAX,
+        }
+    }
+
+    pub(crate) fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Drops the transfer in progress without a response, e.g. on an NMT reset.
+    pub(crate) fn reset(&mut self) {
+        self.transfer = Transfer::Idle;
+        self.deadline = Instant::MAX;
+    }
+
+    /// Time at which the transfer in progress times out, if there is one.
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        match self.transfer {
+            Transfer::Idle => None,
+            _ => Some(self.deadline),
+        }
+    }
+
+    /// Handles a single SDO request and returns the response to send, if any.
+    ///
+    /// During a block upload the segments are not part of the response,
+    /// they are fetched with [`SdoServer::next_block_segment`].
+    pub(crate) fn process_request<const N: usize>(
+        &mut self,
+        od: &mut ObjectDictionary<N>,
+        request: &[u8; 8],
+        now: Instant,
+    ) -> Option<[u8; 8]> {
+        let command = request[0] >> 5;
+
+        // While a block is downloaded every frame except an abort is a segment. The last segment of a block
+        // (0x81 - 0xFF) shares its command specifier with an abort, but sequence number 0 is never a segment.
+        let block_segment = matches!(self.transfer, Transfer::BlockDownload { complete: false, .. })
+            && request[0] != CCS_ABORT << 5;
+
+        let initiate = !block_segment
+            && match command {
+                CCS_INITIATE_DOWNLOAD | CCS_INITIATE_UPLOAD | CCS_ABORT => true,
+                CCS_BLOCK_UPLOAD => request[0] & 0x03 == CS_BLOCK_UPLOAD_INITIATE,
+                CCS_BLOCK_DOWNLOAD => request[0] & 0x01 == CS_BLOCK_DOWNLOAD_INITIATE,
+                _ => false,
+            };
+
+        // Only initiate requests carry a multiplexer, the others belong to the transfer in progress
+        let (index, subindex) = if initiate {
+            (u16::from_le_bytes([request[1], request[2]]), request[3])
+        } else {
+            self.multiplexer()
+        };
+
+        let result = if block_segment {
+            self.block_download_segment(request)
+        } else {
+            match command {
+                CCS_INITIATE_DOWNLOAD => self.initiate_download(od, index, subindex, request).map(Some),
+                CCS_DOWNLOAD_SEGMENT => self.download_segment(od, request).map(Some),
+                CCS_INITIATE_UPLOAD => self.initiate_upload(od, index, subindex).map(Some),
+                CCS_UPLOAD_SEGMENT => self.upload_segment(od, request).map(Some),
+                CCS_BLOCK_DOWNLOAD => match request[0] & 0x01 {
+                    CS_BLOCK_DOWNLOAD_INITIATE => self.initiate_block_download(od, index, subindex, request).map(Some),
+                    CS_BLOCK_DOWNLOAD_END => self.end_block_download(od, request).map(Some),
+                    _ => Err(AbortCode::CommandSpecifierInvalid),
+                },
+                CCS_BLOCK_UPLOAD => match request[0] & 0x03 {
+                    CS_BLOCK_UPLOAD_INITIATE => self.initiate_block_upload(od, index, subindex, request).map(Some),
+                    CS_BLOCK_UPLOAD_START => self.start_block_upload(),
+                    CS_BLOCK_UPLOAD_ACK => self.block_upload_ack(od, request),
+                    CS_BLOCK_UPLOAD_END => self.end_block_upload(),
+                    _ => Err(AbortCode::CommandSpecifierInvalid),
+                },
+                CCS_ABORT => {
+                    // An abort from the client is never answered
+                    self.transfer = Transfer::Idle;
+                    return None;
+                }
+                _ => Err(AbortCode::CommandSpecifierInvalid),
+            }
+        };
+
+        match result {
+            Ok(response) => {
+                self.deadline = now + self.timeout;
+                response
+            }
+            Err(code) => {
+                self.transfer = Transfer::Idle;
+                Some(abort_response(index, subindex, code))
+            }
+        }
+    }
+
+    /// Returns the next segment to send while a block upload is in progress.
+    pub(crate) fn next_block_segment<const N: usize>(&mut self, od: &ObjectDictionary<N>, now: Instant) -> Option<[u8; 8]> {
+        let Transfer::BlockUpload { index, subindex, size, blksize, ref mut offset, ref mut seqno, ref mut phase, .. } = self.transfer else {
+            return None;
+        };
+
+        if *phase != BlockUploadPhase::Sending {
+            return None;
+        }
+
+        let value = match od.get_entry(index, subindex).and_then(|entry| entry.read()) {
+            Ok(value) => value,
+            Err(error) => {
+                self.transfer = Transfer::Idle;
+                return Some(abort_response(index, subindex, error.into()));
+            }
+        };
+
+        let mut segment = [0u8; 8];
+        let remaining = size.saturating_sub(*offset).min(7);
+        *offset += value.read_bytes(*offset, &mut segment[1..1 + remaining]);
+        *seqno += 1;
+
+        let last = *offset >= size;
+        segment[0] = ((last as u8) << 7) | *seqno;
+
+        if last || *seqno >= blksize {
+            *phase = BlockUploadPhase::WaitingForAck;
+        }
+
+        self.deadline = now + self.timeout;
+        Some(segment)
+    }
+
+    /// Aborts the transfer in progress if it timed out and returns the abort frame to send.
+    pub(crate) fn process_timeout(&mut self, now: Instant) -> Option<[u8; 8]> {
+        if self.deadline().is_some_and(|deadline| deadline <= now) {
+            let (index, subindex) = self.multiplexer();
+            self.transfer = Transfer::Idle;
+            Some(abort_response(index, subindex, AbortCode::ProtocolTimedOut))
+        } else {
+            None
+        }
+    }
+
+    fn multiplexer(&self) -> (u16, u8) {
+        match self.transfer {
+            Transfer::Idle => (0, 0),
+            Transfer::Download { index, subindex, .. }
+            | Transfer::Upload { index, subindex, .. }
+            | Transfer::BlockDownload { index, subindex, .. }
+            | Transfer::BlockUpload { index, subindex, .. } => (index, subindex),
+        }
+    }
+
+    fn initiate_download<const N: usize>(
+        &mut self,
+        od: &mut ObjectDictionary<N>,
+        index: u16,
+        subindex: u8,
+        request: &[u8; 8],
+    ) -> Result<[u8; 8], AbortCode> {
+        // A new request always replaces the transfer in progress
+        self.transfer = Transfer::Idle;
+
+        let command = request[0];
+        let expedited = command & 0x02 != 0;
+        let size_indicated = command & 0x01 != 0;
+
+        let entry = od.get_entry(index, subindex)?;
+        let mut target = DownloadTarget::for_entry(entry)?;
+
+        if expedited {
+            let len = match (size_indicated, &target) {
+                (true, _) => 4 - ((command >> 2) & 0x03) as usize,
+                (false, DownloadTarget::Fixed { data_type, .. }) => data_type.size().unwrap_or(4),
+                (false, DownloadTarget::Domain(_)) => 4,
+            };
+
+            target.check_size(len)?;
+            target.start();
+            target.write(0, &request[4..4 + len])?;
+            target.finish(od, index, subindex, len)?;
+        } else {
+            let size = if size_indicated {
+                let size = u32::from_le_bytes([request[4], request[5], request[6], request[7]]) as usize;
+                target.check_size(size)?;
+                Some(size)
+            } else {
+                None
+            };
+            target.start();
+
+            self.transfer = Transfer::Download {
+                index,
+                subindex,
+                target,
+                size,
+                offset: 0,
+                toggle: false,
+            };
+        }
+
+        Ok(response(SCS_INITIATE_DOWNLOAD << 5, index, subindex, [0; 4]))
+    }
+
+    fn download_segment<const N: usize>(
+        &mut self,
+        od: &mut ObjectDictionary<N>,
+        request: &[u8; 8],
+    ) -> Result<[u8; 8], AbortCode> {
+        let Transfer::Download { index, subindex, ref mut target, size, ref mut offset, ref mut toggle } = self.transfer else {
+            return Err(AbortCode::CommandSpecifierInvalid);
+        };
+
+        let command = request[0];
+        if (command & 0x10 != 0) != *toggle {
+            return Err(AbortCode::ToggleBitNotAlternated);
+        }
+
+        let len = 7 - ((command >> 1) & 0x07) as usize;
+        let last = command & 0x01 != 0;
+
+        target.write(*offset, &request[1..1 + len])?;
+        *offset += len;
+
+        let response = [(SCS_DOWNLOAD_SEGMENT << 5) | ((*toggle as u8) << 4), 0, 0, 0, 0, 0, 0, 0];
+        *toggle = !*toggle;
+
+        if last {
+            match size {
+                Some(size) if *offset > size => return Err(AbortCode::LengthTooHigh),
+                Some(size) if *offset < size => return Err(AbortCode::LengthTooLow),
+                _ => (),
+            }
+
+            target.finish(od, index, subindex, *offset)?;
+            self.transfer = Transfer::Idle;
+        }
+
+        Ok(response)
+    }
+
+    fn initiate_upload<const N: usize>(
+        &mut self,
+        od: &ObjectDictionary<N>,
+        index: u16,
+        subindex: u8,
+    ) -> Result<[u8; 8], AbortCode> {
+        self.transfer = Transfer::Idle;
+
+        let entry = od.get_entry(index, subindex)?;
+        let value = entry.read()?;
+        let size = value.size();
+
+        if (1..=4).contains(&size) {
+            let mut data = [0; 4];
+            value.read_bytes(0, &mut data);
+
+            // Expedited transfer with size indicated, n = number of bytes not containing data
+            let command = (SCS_INITIATE_UPLOAD << 5) | (((4 - size) as u8) << 2) | 0x03;
+            Ok(response(command, index, subindex, data))
+        } else {
+            self.transfer = Transfer::Upload {
+                index,
+                subindex,
+                size,
+                offset: 0,
+                toggle: false,
+            };
+
+            // Segmented transfer with size indicated
+            let command = (SCS_INITIATE_UPLOAD << 5) | 0x01;
+            Ok(response(command, index, subindex, (size as u32).to_le_bytes()))
+        }
+    }
+
+    fn upload_segment<const N: usize>(&mut self, od: &ObjectDictionary<N>, request: &[u8; 8]) -> Result<[u8; 8], AbortCode> {
+        let Transfer::Upload { index, subindex, size, ref mut offset, ref mut toggle } = self.transfer else {
+            return Err(AbortCode::CommandSpecifierInvalid);
+        };
+
+        if (request[0] & 0x10 != 0) != *toggle {
+            return Err(AbortCode::ToggleBitNotAlternated);
+        }
+
+        // Every segment is read through the object dictionary so it reflects the current content
+        let entry = od.get_entry(index, subindex)?;
+        let value = entry.read()?;
+
+        let mut response = [0u8; 8];
+        let remaining = size.saturating_sub(*offset).min(7);
+        let len = value.read_bytes(*offset, &mut response[1..1 + remaining]);
+        *offset += len;
+
+        let last = len < 7 || *offset >= size;
+        response[0] = (SCS_UPLOAD_SEGMENT << 5) | ((*toggle as u8) << 4) | (((7 - len) as u8) << 1) | last as u8;
+        *toggle = !*toggle;
+
+        if last {
+            self.transfer = Transfer::Idle;
+        }
+
+        Ok(response)
+    }
+
+    fn initiate_block_download<const N: usize>(
+        &mut self,
+        od: &mut ObjectDictionary<N>,
+        index: u16,
+        subindex: u8,
+        request: &[u8; 8],
+    ) -> Result<[u8; 8], AbortCode> {
+        self.transfer = Transfer::Idle;
+
+        let client_crc = request[0] & 0x04 != 0;
+        let size_indicated = request[0] & 0x02 != 0;
+
+        let entry = od.get_entry(index, subindex)?;
+        let target = DownloadTarget::for_entry(entry)?;
+
+        let size = if size_indicated {
+            let size = u32::from_le_bytes([request[4], request[5], request[6], request[7]]) as usize;
+            target.check_size(size)?;
+            Some(size)
+        } else {
+            None
+        };
+        target.start();
+
+        self.transfer = Transfer::BlockDownload {
+            index,
+            subindex,
+            target,
+            size,
+            offset: 0,
+            crc: client_crc.then_some(0),
+            seqno: 0,
+            pending: None,
+            complete: false,
+        };
+
+        // The server always supports CRC
+        let command = (SCS_BLOCK_DOWNLOAD << 5) | 0x04 | SS_BLOCK_INITIATE;
+        Ok(response(command, index, subindex, [BLOCK_SIZE, 0, 0, 0]))
+    }
+
+    fn block_download_segment(&mut self, request: &[u8; 8]) -> Result<Option<[u8; 8]>, AbortCode> {
+        let Transfer::BlockDownload { ref mut target, ref mut offset, ref mut crc, ref mut seqno, ref mut pending, ref mut complete, .. } = self.transfer else {
+            return Err(AbortCode::CommandSpecifierInvalid);
+        };
+
+        let last = request[0] & 0x80 != 0;
+        let received = request[0] & 0x7F;
+
+        // Segments out of sequence are dropped, the client repeats them after the acknowledge
+        if received == *seqno + 1 {
+            if let Some(data) = pending.take() {
+                block_download_write(target, offset, crc, &data)?;
+            }
+
+            let mut data = [0; 7];
+            data.copy_from_slice(&request[1..]);
+            *pending = Some(data);
+            *seqno = received;
+            *complete = last;
+        }
+
+        if received >= BLOCK_SIZE || last {
+            // Acknowledge the last segment received in sequence, the next block starts after it
+            let command = (SCS_BLOCK_DOWNLOAD << 5) | SS_BLOCK_ACK;
+            let response = [command, *seqno, BLOCK_SIZE, 0, 0, 0, 0, 0];
+            *seqno = 0;
+            Ok(Some(response))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn end_block_download<const N: usize>(&mut self, od: &mut ObjectDictionary<N>, request: &[u8; 8]) -> Result<[u8; 8], AbortCode> {
+        let Transfer::BlockDownload { index, subindex, ref mut target, size, ref mut offset, ref mut crc, ref mut pending, complete: true, .. } = self.transfer else {
+            return Err(AbortCode::CommandSpecifierInvalid);
+        };
+
+        // n = number of bytes in the last segment that do not contain data
+        let unused = ((request[0] >> 2) & 0x07) as usize;
+        if let Some(data) = pending.take() {
+            block_download_write(target, offset, crc, &data[..7 - unused])?;
+        }
+
+        match size {
+            Some(size) if *offset > size => return Err(AbortCode::LengthTooHigh),
+            Some(size) if *offset < size => return Err(AbortCode::LengthTooLow),
+            _ => (),
+        }
+
+        if crc.is_some_and(|crc| crc != u16::from_le_bytes([request[1], request[2]])) {
+            return Err(AbortCode::CrcError);
+        }
+
+        target.finish(od, index, subindex, *offset)?;
+        self.transfer = Transfer::Idle;
+
+        Ok(response((SCS_BLOCK_DOWNLOAD << 5) | SS_BLOCK_END, 0, 0, [0; 4]))
+    }
+
+    fn initiate_block_upload<const N: usize>(
+        &mut self,
+        od: &ObjectDictionary<N>,
+        index: u16,
+        subindex: u8,
+        request: &[u8; 8],
+    ) -> Result<[u8; 8], AbortCode> {
+        self.transfer = Transfer::Idle;
+
+        let client_crc = request[0] & 0x04 != 0;
+        let blksize = request[4];
+        let protocol_switch_threshold = request[5] as usize;
+
+        if !(1..=127).contains(&blksize) {
+            return Err(AbortCode::InvalidBlockSize);
+        }
+
+        let entry = od.get_entry(index, subindex)?;
+        let size = entry.read()?.size();
+
+        // Small objects are cheaper to transfer without block overhead if the client allows it (0 = not allowed)
+        if protocol_switch_threshold != 0 && size <= protocol_switch_threshold {
+            return self.initiate_upload(od, index, subindex);
+        }
+
+        self.transfer = Transfer::BlockUpload {
+            index,
+            subindex,
+            size,
+            crc: client_crc,
+            blksize,
+            block_start: 0,
+            offset: 0,
+            seqno: 0,
+            phase: BlockUploadPhase::Initiated,
+        };
+
+        // The server always supports CRC, size indicated
+        let command = (SCS_BLOCK_UPLOAD << 5) | 0x04 | 0x02 | SS_BLOCK_INITIATE;
+        Ok(response(command, index, subindex, (size as u32).to_le_bytes()))
+    }
+
+    fn start_block_upload(&mut self) -> Result<Option<[u8; 8]>, AbortCode> {
+        let Transfer::BlockUpload { ref mut phase, .. } = self.transfer else {
+            return Err(AbortCode::CommandSpecifierInvalid);
+        };
+
+        if *phase != BlockUploadPhase::Initiated {
+            return Err(AbortCode::CommandSpecifierInvalid);
+        }
+
+        *phase = BlockUploadPhase::Sending;
+        Ok(None)
+    }
+
+    fn block_upload_ack<const N: usize>(&mut self, od: &ObjectDictionary<N>, request: &[u8; 8]) -> Result<Option<[u8; 8]>, AbortCode> {
+        let Transfer::BlockUpload { index, subindex, size, crc, ref mut blksize, ref mut block_start, ref mut offset, ref mut seqno, ref mut phase } = self.transfer else {
+            return Err(AbortCode::CommandSpecifierInvalid);
+        };
+
+        if *phase != BlockUploadPhase::WaitingForAck {
+            return Err(AbortCode::CommandSpecifierInvalid);
+        }
+
+        let ackseq = request[1];
+        let next_blksize = request[2];
+
+        if ackseq > *seqno {
+            return Err(AbortCode::InvalidSequenceNumber);
+        }
+        if !(1..=127).contains(&next_blksize) {
+            return Err(AbortCode::InvalidBlockSize);
+        }
+
+        let acknowledged = *block_start + ackseq as usize * 7;
+
+        if ackseq > 0 && acknowledged >= size {
+            *phase = BlockUploadPhase::WaitingForEnd;
+
+            let crc = if crc { upload_crc(od, index, subindex, size)? } else { 0 };
+            // n = number of bytes in the last segment that do not contain data
+            let unused = if size == 0 { 7 } else { (7 - size % 7) % 7 };
+
+            let command = (SCS_BLOCK_UPLOAD << 5) | ((unused as u8) << 2) | SS_BLOCK_END;
+            let crc = crc.to_le_bytes();
+            return Ok(Some([command, crc[0], crc[1], 0, 0, 0, 0, 0]));
+        }
+
+        // Continue, or repeat the segments not received, with the next block
+        *block_start = acknowledged;
+        *offset = acknowledged;
+        *seqno = 0;
+        *blksize = next_blksize;
+        *phase = BlockUploadPhase::Sending;
+        Ok(None)
+    }
+
+    fn end_block_upload(&mut self) -> Result<Option<[u8; 8]>, AbortCode> {
+        match self.transfer {
+            Transfer::BlockUpload { phase: BlockUploadPhase::WaitingForEnd, .. } => {
+                self.transfer = Transfer::Idle;
+                Ok(None)
+            }
+            _ => Err(AbortCode::CommandSpecifierInvalid),
+        }
+    }
+}
+
+fn block_download_write(
+    target: &mut DownloadTarget,
+    offset: &mut usize,
+    crc: &mut Option<u16>,
+    data: &[u8],
+) -> Result<(), AbortCode> {
+    target.write(*offset, data)?;
+    *offset += data.len();
+
+    if let Some(crc) = crc {
+        *crc = crc16(*crc, data);
+    }
+
+    Ok(())
+}
+
+fn upload_crc<const N: usize>(od: &ObjectDictionary<N>, index: u16, subindex: u8, size: usize) -> Result<u16, AbortCode> {
+    let entry = od.get_entry(index, subindex)?;
+    let value = entry.read()?;
+
+    let mut crc = 0;
+    let mut offset = 0;
+    let mut buf = [0u8; 7];
+    while offset < size {
+        let len = value.read_bytes(offset, &mut buf[..(size - offset).min(7)]);
+        if len == 0 {
+            break;
+        }
+
+        crc = crc16(crc, &buf[..len]);
+        offset += len;
+    }
+
+    Ok(crc)
+}
+
+/// CRC-16 as used by SDO block transfers (CCITT polynomial x^16 + x^12 + x^5 + 1, initial value 0).
+pub(crate) fn crc16(crc: u16, data: &[u8]) -> u16 {
+    data.iter().fold(crc, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+pub(crate) fn response(command: u8, index: u16, subindex: u8, data: [u8; 4]) -> [u8; 8] {
+    let index = index.to_le_bytes();
+    [command, index[0], index[1], subindex, data[0], data[1], data[2], data[3]]
+}
+
+pub(crate) fn abort_response(index: u16, subindex: u8, code: AbortCode) -> [u8; 8] {
+    response(SCS_ABORT << 5, index, subindex, u32::from(code).to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object_dictionary::{AccessType, Config};
+
+    fn od_with_domain() -> (ObjectDictionary<256>, &'static Domain) {
+        let domain: &'static Domain = Box::leak(Box::new(Domain::new(Box::leak(Box::new([0; 64])))));
+        let mut od = ObjectDictionary::new_canopen_301(Config::default());
+        od.add_entry(ObjectDictionaryEntry::new(0x2000, 0, DataType::Domain, AccessType::ReadWrite, Value::Domain(domain)));
+        (od, domain)
+    }
+
+    // Downloads `data` to 0x2000:0 in a single block, returns the responses to the segments and to the end request
+    fn block_download(data: &[u8]) -> (Vec<Option<[u8; 8]>>, &'static Domain) {
+        let (mut od, domain) = od_with_domain();
+        let mut server = SdoServer::new(DEFAULT_SDO_TIMEOUT);
+        let now = Instant::from_ticks(0);
+
+        let size = (data.len() as u32).to_le_bytes();
+        let initiate = [(CCS_BLOCK_DOWNLOAD << 5) | 0x02, 0x00, 0x20, 0x00, size[0], size[1], size[2], size[3]];
+        let response = server.process_request(&mut od, &initiate, now).unwrap();
+        assert_eq!(response[0], (SCS_BLOCK_DOWNLOAD << 5) | 0x04 | SS_BLOCK_INITIATE);
+
+        let segments = data.chunks(7).count();
+        let mut responses = Vec::new();
+        for (n, chunk) in data.chunks(7).enumerate() {
+            let mut segment = [0; 8];
+            segment[0] = (n + 1) as u8 | if n + 1 == segments { 0x80 } else { 0 };
+            segment[1..1 + chunk.len()].copy_from_slice(chunk);
+            responses.push(server.process_request(&mut od, &segment, now));
+        }
+
+        let unused = (7 - data.len() % 7) % 7;
+        let end = [(CCS_BLOCK_DOWNLOAD << 5) | ((unused as u8) << 2) | CS_BLOCK_DOWNLOAD_END, 0, 0, 0, 0, 0, 0, 0];
+        responses.push(server.process_request(&mut od, &end, now));
+
+        (responses, domain)
+    }
+
+    #[test]
+    fn block_download_with_short_last_block() {
+        for len in [10, 20] {
+            let data: Vec<u8> = (1..=len as u8).collect();
+            let (responses, domain) = block_download(&data);
+            let [segments @ .., ack, end] = responses.as_slice() else {
+                panic!("no segments sent");
+            };
+
+            // Only the last segment of the block is acknowledged
+            assert!(segments.iter().all(Option::is_none));
+            assert_eq!(ack.unwrap()[..3], [(SCS_BLOCK_DOWNLOAD << 5) | SS_BLOCK_ACK, segments.len() as u8 + 1, BLOCK_SIZE]);
+            assert_eq!(end.unwrap()[0], (SCS_BLOCK_DOWNLOAD << 5) | SS_BLOCK_END);
+
+            let mut content = [0; 64];
+            assert_eq!(domain.read(0, &mut content), len);
+            assert_eq!(content[..len], data[..]);
+        }
+    }
+
+    #[test]
+    fn block_download_abort() {
+        let (mut od, domain) = od_with_domain();
+        let mut server = SdoServer::new(DEFAULT_SDO_TIMEOUT);
+        let now = Instant::from_ticks(0);
+
+        let initiate = [CCS_BLOCK_DOWNLOAD << 5, 0x00, 0x20, 0x00, 0, 0, 0, 0];
+        server.process_request(&mut od, &initiate, now).unwrap();
+        assert_eq!(server.process_request(&mut od, &[0x01, 1, 2, 3, 4, 5, 6, 7], now), None);
+
+        let abort = abort_response(0x2000, 0, AbortCode::GeneralError);
+        assert_eq!(server.process_request(&mut od, &abort, now), None);
+        assert!(server.deadline().is_none());
+        assert!(domain.is_empty());
+    }
+
+    #[test]
+    fn rejected_download_keeps_domain() {
+        let (mut od, domain) = od_with_domain();
+        domain.write(0, &[1, 2, 3]).unwrap();
+        let mut server = SdoServer::new(DEFAULT_SDO_TIMEOUT);
+        let now = Instant::from_ticks(0);
+
+        // 65 bytes do not fit into the domain
+        let initiate = [(CCS_INITIATE_DOWNLOAD << 5) | 0x01, 0x00, 0x20, 0x00, 65, 0, 0, 0];
+        let block_initiate = [(CCS_BLOCK_DOWNLOAD << 5) | 0x02, 0x00, 0x20, 0x00, 65, 0, 0, 0];
+        for request in [initiate, block_initiate] {
+            let response = server.process_request(&mut od, &request, now).unwrap();
+            assert_eq!(response, abort_response(0x2000, 0, AbortCode::LengthTooHigh));
+            assert_eq!(domain.len(), 3);
+        }
+    }
+
+    #[test]
+    fn sync_counter_overflow_rejects_reserved_value() {
+        let (mut od, _) = od_with_domain();
+        let mut server = SdoServer::new(DEFAULT_SDO_TIMEOUT);
+        let now = Instant::from_ticks(0);
+
+        // Expedited download of 1 byte to 0x1019:0
+        let download = |value| [(CCS_INITIATE_DOWNLOAD << 5) | (3 << 2) | 0x03, 0x19, 0x10, 0x00, value, 0, 0, 0];
+        let response = server.process_request(&mut od, &download(1), now).unwrap();
+        assert_eq!(response, abort_response(0x1019, 0, AbortCode::ValueRangeExceeded));
+
+        let response = server.process_request(&mut od, &download(2), now).unwrap();
+        assert_eq!(response[0], SCS_INITIATE_DOWNLOAD << 5);
+        assert_eq!(od.get_value(0x1019, 0).unwrap().as_u32(), Some(2));
+    }
+
+    #[test]
+    fn block_upload_without_protocol_switch() {
+        let (mut od, _) = od_with_domain();
+        let mut server = SdoServer::new(DEFAULT_SDO_TIMEOUT);
+
+        // Empty domain, protocol switch threshold 0 = not allowed
+        let initiate = [(CCS_BLOCK_UPLOAD << 5) | CS_BLOCK_UPLOAD_INITIATE, 0x00, 0x20, 0x00, 127, 0, 0, 0];
+        let response = server.process_request(&mut od, &initiate, Instant::from_ticks(0)).unwrap();
+        assert_eq!(response[0], (SCS_BLOCK_UPLOAD << 5) | 0x04 | 0x02 | SS_BLOCK_INITIATE);
+        assert_eq!(response[4..], [0; 4]);
+    }
+}
@@ -0,0 +1,196 @@
+use embassy_futures::select::{select, Either};
+use embassy_stm32::can::Frame;
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::{Receiver, Sender}};
+use embassy_time::{Duration, Instant, Timer};
+use heapless::Vec;
+
+use crate::sdo::{
+    abort_response, response, AbortCode, CCS_DOWNLOAD_SEGMENT, CCS_INITIATE_DOWNLOAD, CCS_INITIATE_UPLOAD,
+    CCS_UPLOAD_SEGMENT, SCS_ABORT, SCS_DOWNLOAD_SEGMENT, SCS_INITIATE_DOWNLOAD, SCS_INITIATE_UPLOAD, SCS_UPLOAD_SEGMENT,
+};
+
+/// Number of SDO responses buffered between the node and the client.
+pub const SDO_CLIENT_QUEUE_SIZE: usize = 4;
+
+/// Time the client waits for each response of the server.
+pub const DEFAULT_SDO_CLIENT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SdoClientError {
+    /// The server aborted the transfer.
+    ServerAbort(AbortCode),
+    /// The client aborted the transfer, e.g. because the server did not respond in time.
+    ClientAbort(AbortCode),
+}
+
+/// Client for expedited and segmented SDO transfers to other nodes.
+///
+/// Responses are routed to the client by [`Node::process`](crate::node::Node::process),
+/// so the node has to be running for a transfer to complete.
+pub struct SdoClient<'b, const R: usize> {
+    pub(crate) can_tx_sender: Sender<'b, ThreadModeRawMutex, Frame, R>,
+    pub(crate) responses: Receiver<'b, ThreadModeRawMutex, Frame, SDO_CLIENT_QUEUE_SIZE>,
+    pub(crate) timeout: Duration,
+}
+
+impl<'b, const R: usize> SdoClient<'b, R> {
+    /// Sets how long the client waits for each response before aborting the transfer.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Reads an entry of a remote node.
+    pub async fn upload<const S: usize>(&mut self, node_id: u8, index: u16, subindex: u8) -> Result<Vec<u8, S>, SdoClientError> {
+        self.flush();
+
+        let request = response(CCS_INITIATE_UPLOAD << 5, index, subindex, [0; 4]);
+        let initiate = self.request(node_id, index, subindex, &request).await?;
+        if !is_initiate_response(&initiate, SCS_INITIATE_UPLOAD, index, subindex) {
+            return Err(self.abort(node_id, index, subindex, AbortCode::CommandSpecifierInvalid).await);
+        }
+
+        let command = initiate[0];
+        let size_indicated = command & 0x01 != 0;
+        let mut data = Vec::new();
+
+        if command & 0x02 != 0 {
+            // Expedited, the data is part of the initiate response
+            let len = if size_indicated { 4 - ((command >> 2) & 0x03) as usize } else { 4 };
+            if data.extend_from_slice(&initiate[4..4 + len]).is_err() {
+                return Err(self.abort(node_id, index, subindex, AbortCode::OutOfMemory).await);
+            }
+            return Ok(data);
+        }
+
+        let size = size_indicated.then(|| u32::from_le_bytes([initiate[4], initiate[5], initiate[6], initiate[7]]) as usize);
+        if size.is_some_and(|size| size > S) {
+            return Err(self.abort(node_id, index, subindex, AbortCode::OutOfMemory).await);
+        }
+
+        let mut toggle = false;
+        loop {
+            let request = [(CCS_UPLOAD_SEGMENT << 5) | ((toggle as u8) << 4), 0, 0, 0, 0, 0, 0, 0];
+            let segment = self.request(node_id, index, subindex, &request).await?;
+
+            if segment[0] >> 5 != SCS_UPLOAD_SEGMENT {
+                return Err(self.abort(node_id, index, subindex, AbortCode::CommandSpecifierInvalid).await);
+            }
+            if (segment[0] & 0x10 != 0) != toggle {
+                return Err(self.abort(node_id, index, subindex, AbortCode::ToggleBitNotAlternated).await);
+            }
+
+            let len = 7 - ((segment[0] >> 1) & 0x07) as usize;
+            if data.extend_from_slice(&segment[1..1 + len]).is_err() {
+                return Err(self.abort(node_id, index, subindex, AbortCode::OutOfMemory).await);
+            }
+
+            if segment[0] & 0x01 != 0 {
+                break;
+            }
+            toggle = !toggle;
+        }
+
+        if size.is_some_and(|size| size != data.len()) {
+            return Err(SdoClientError::ClientAbort(AbortCode::LengthMismatch));
+        }
+
+        Ok(data)
+    }
+
+    /// Writes an entry of a remote node.
+    pub async fn download(&mut self, node_id: u8, index: u16, subindex: u8, data: &[u8]) -> Result<(), SdoClientError> {
+        self.flush();
+
+        if (1..=4).contains(&data.len()) {
+            // Expedited with size indicated, n = number of bytes not containing data
+            let mut payload = [0; 4];
+            payload[..data.len()].copy_from_slice(data);
+            let command = (CCS_INITIATE_DOWNLOAD << 5) | (((4 - data.len()) as u8) << 2) | 0x03;
+
+            let initiate = self.request(node_id, index, subindex, &response(command, index, subindex, payload)).await?;
+            if !is_initiate_response(&initiate, SCS_INITIATE_DOWNLOAD, index, subindex) {
+                return Err(self.abort(node_id, index, subindex, AbortCode::CommandSpecifierInvalid).await);
+            }
+            return Ok(());
+        }
+
+        // Segmented with size indicated
+        let command = (CCS_INITIATE_DOWNLOAD << 5) | 0x01;
+        let size = (data.len() as u32).to_le_bytes();
+        let initiate = self.request(node_id, index, subindex, &response(command, index, subindex, size)).await?;
+        if !is_initiate_response(&initiate, SCS_INITIATE_DOWNLOAD, index, subindex) {
+            return Err(self.abort(node_id, index, subindex, AbortCode::CommandSpecifierInvalid).await);
+        }
+
+        let mut offset = 0;
+        let mut toggle = false;
+        loop {
+            let len = (data.len() - offset).min(7);
+            let last = offset + len >= data.len();
+
+            let mut request = [0u8; 8];
+            request[0] = (CCS_DOWNLOAD_SEGMENT << 5) | ((toggle as u8) << 4) | (((7 - len) as u8) << 1) | last as u8;
+            request[1..1 + len].copy_from_slice(&data[offset..offset + len]);
+
+            let segment = self.request(node_id, index, subindex, &request).await?;
+            if segment[0] >> 5 != SCS_DOWNLOAD_SEGMENT {
+                return Err(self.abort(node_id, index, subindex, AbortCode::CommandSpecifierInvalid).await);
+            }
+            if (segment[0] & 0x10 != 0) != toggle {
+                return Err(self.abort(node_id, index, subindex, AbortCode::ToggleBitNotAlternated).await);
+            }
+
+            offset += len;
+            if last {
+                return Ok(());
+            }
+            toggle = !toggle;
+        }
+    }
+
+    // Sends a request to the server of `node_id` and waits for its response
+    async fn request(&mut self, node_id: u8, index: u16, subindex: u8, request: &[u8; 8]) -> Result<[u8; 8], SdoClientError> {
+        let msg = Frame::new_standard(0x600 + node_id as u16, request).unwrap();
+        self.can_tx_sender.send(msg).await;
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let frame = match select(self.responses.receive(), Timer::at(deadline)).await {
+                Either::First(frame) => frame,
+                Either::Second(_) => return Err(self.abort(node_id, index, subindex, AbortCode::ProtocolTimedOut).await),
+            };
+
+            // Responses of other servers, e.g. to a transfer that was given up on, are dropped
+            let from_server = matches!(frame.id(), embedded_can::Id::Standard(id) if id.as_raw() == 0x580 + node_id as u16);
+            let Ok(response) = <[u8; 8]>::try_from(frame.data()) else {
+                continue;
+            };
+            if !from_server {
+                continue;
+            }
+
+            if response[0] >> 5 == SCS_ABORT {
+                let code = u32::from_le_bytes([response[4], response[5], response[6], response[7]]);
+                return Err(SdoClientError::ServerAbort(code.into()));
+            }
+
+            return Ok(response);
+        }
+    }
+
+    async fn abort(&self, node_id: u8, index: u16, subindex: u8, code: AbortCode) -> SdoClientError {
+        let msg = Frame::new_standard(0x600 + node_id as u16, &abort_response(index, subindex, code)).unwrap();
+        self.can_tx_sender.send(msg).await;
+        SdoClientError::ClientAbort(code)
+    }
+
+    // Drops responses left over from an earlier transfer
+    fn flush(&mut self) {
+        while self.responses.try_receive().is_ok() {}
+    }
+}
+
+fn is_initiate_response(response: &[u8; 8], command: u8, index: u16, subindex: u8) -> bool {
+    response[0] >> 5 == command && u16::from_le_bytes([response[1], response[2]]) == index && response[3] == subindex
+}
@@ -0,0 +1,83 @@
+use embassy_futures::select::{select, Either};
+use embassy_stm32::can::Frame;
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::Sender, mutex::Mutex, signal::Signal};
+use embassy_time::{Duration, Ticker, Timer};
+
+use crate::{nmt::{NmtService, NmtState}, node::Context, object_dictionary::ObjectDictionary};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct SyncConfig {
+    cob_id: u16,
+    period: Duration,
+    overflow: u8,
+}
+
+/// Sends SYNC messages when 0x1005 marks the node as SYNC producer (bit 30).
+pub struct SyncProducer<'a, 'b, 'c, const N: usize, const R: usize> {
+    pub(crate) context: &'c Mutex<ThreadModeRawMutex, Context>,
+    pub(crate) object_dictionary: &'a Mutex<ThreadModeRawMutex, ObjectDictionary<N>>,
+    pub(crate) can_tx_sender: Sender<'b, ThreadModeRawMutex, Frame, R>,
+    pub(crate) local_sync: &'b Signal<ThreadModeRawMutex, Option<u8>>,
+    pub(crate) nmt_state_changed: &'b Signal<ThreadModeRawMutex, NmtState>,
+}
+
+impl<'a, 'b, 'c, const N: usize, const R: usize> SyncProducer<'a, 'b, 'c, N, R> {
+    // COB-ID (0x1005), communication cycle period (0x1006) and counter overflow value (0x1019),
+    // `None` if the node is not the SYNC producer
+    async fn config(&self) -> Option<SyncConfig> {
+        let locked_od = self.object_dictionary.lock().await;
+        let cob_id = locked_od.get_value(0x1005, 0).ok()?.as_u32()?;
+        let period = locked_od.get_value(0x1006, 0).ok()?.as_u32()?;
+        let overflow = locked_od.get_value(0x1019, 0).ok().and_then(|value| value.as_u32()).unwrap_or(0);
+
+        if cob_id & 0x4000_0000 == 0 || period == 0 {
+            return None;
+        }
+
+        Some(SyncConfig {
+            cob_id: (cob_id & 0x7FF) as u16,
+            period: Duration::from_micros(period as u64),
+            overflow: overflow as u8,
+        })
+    }
+
+    /// Runs the producer. While it is disabled the configuration is re-read every `idle_timeout`.
+    pub async fn run(&self, idle_timeout: Duration) -> ! {
+        loop {
+            let Some(config) = self.config().await else {
+                Timer::after(idle_timeout).await;
+                continue;
+            };
+
+            // The counter runs from 1 to the overflow value, 0 = no counter
+            let mut counter = 1;
+            let mut ticker = Ticker::every(config.period);
+
+            loop {
+                // The cycle and the counter restart with every NMT state change
+                if let Either::Second(_) = select(ticker.next(), self.nmt_state_changed.wait()).await {
+                    break;
+                }
+
+                // Restart with the new period when the configuration changed
+                if self.config().await != Some(config) {
+                    break;
+                }
+
+                let nmt_state = self.context.lock().await.nmt_state;
+                if !nmt_state.allows(NmtService::Sync) {
+                    continue;
+                }
+
+                let counter_value = (config.overflow > 1).then_some(counter);
+                let msg = Frame::new_standard(config.cob_id, counter_value.as_slice()).unwrap();
+                self.can_tx_sender.send(msg).await;
+
+                // The node does not receive its own frames
+                self.local_sync.signal(counter_value);
+
+                counter = if counter >= config.overflow { 1 } else { counter + 1 };
+            }
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use defmt::warn;
+use embassy_futures::select::{select3, Either3};
+use embassy_stm32::can::Frame;
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::{Receiver, Sender}, mutex::Mutex, signal::Signal};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::{
+    nmt::{NmtService, NmtState},
+    node::Context,
+    object_dictionary::ObjectDictionary,
+    pdo::{self, PdoCobId, TransmissionType, TPDO_COMMUNICATION, TPDO_MAPPING},
+};
+
+/// Number of requests buffered for the TPDO producer.
+pub const TPDO_QUEUE_SIZE: usize = 8;
+
+/// Number of synchronous TPDOs buffered for the sender, each with the end of its synchronous window.
+pub const SYNC_TX_QUEUE_SIZE: usize = 8;
+
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum TpdoRequest {
+    /// The mapped application data of a TPDO changed.
+    Event(u16),
+    /// A SYNC was received, with its counter if the SYNC producer uses one
+    /// and the end of the synchronous window if 0x1007 is set.
+    Sync { counter: Option<u8>, window_end: Option<Instant> },
+    /// A remote frame requested a TPDO.
+    Rtr(u16),
+}
+
+/// Signals application events to the TPDO producer.
+#[derive(Copy, Clone)]
+pub struct TpdoTrigger<'b> {
+    pub(crate) requests: Sender<'b, ThreadModeRawMutex, TpdoRequest, TPDO_QUEUE_SIZE>,
+}
+
+impl TpdoTrigger<'_> {
+    /// Signals that the mapped data of TPDO `tpdo` (0-based) changed.
+    ///
+    /// Event-driven TPDOs are sent right away, acyclic synchronous ones with the next SYNC.
+    pub async fn trigger(&self, tpdo: u16) {
+        self.requests.send(TpdoRequest::Event(tpdo)).await;
+    }
+}
+
+#[derive(Copy, Clone)]
+struct TpdoState {
+    sync_count: u8,
+    /// A cyclic TPDO with SYNC start value waits for the matching SYNC counter.
+    sync_started: bool,
+    event_pending: bool,
+    sampled: Option<Frame>,
+    /// Earliest time an event-driven TPDO may be sent again.
+    inhibited_until: Instant,
+    /// An event arrived during the inhibit time.
+    inhibited_event: bool,
+    /// Time the event timer elapses, restarted with every transmission.
+    event_timer: Option<Instant>,
+}
+
+impl TpdoState {
+    fn new() -> Self {
+        Self {
+            sync_count: 0,
+            sync_started: false,
+            event_pending: false,
+            sampled: None,
+            inhibited_until: Instant::MIN,
+            inhibited_event: false,
+            event_timer: None,
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        let inhibit_end = self.inhibited_event.then_some(self.inhibited_until);
+        match (inhibit_end, self.event_timer) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// Inhibit time (sub-index 3) and event timer (sub-index 5) of an event-driven TPDO.
+struct Timing {
+    inhibit_time: Duration,
+    event_timer: Option<Duration>,
+}
+
+/// Sends the first `T` TPDOs configured in 0x1800 + n (communication) and 0x1A00 + n (mapping).
+pub struct TpdoProducer<'a, 'b, 'c, const N: usize, const R: usize, const T: usize> {
+    pub(crate) context: &'c Mutex<ThreadModeRawMutex, Context>,
+    pub(crate) object_dictionary: &'a Mutex<ThreadModeRawMutex, ObjectDictionary<N>>,
+    pub(crate) can_tx_sender: Sender<'b, ThreadModeRawMutex, Frame, R>,
+    pub(crate) requests: Receiver<'b, ThreadModeRawMutex, TpdoRequest, TPDO_QUEUE_SIZE>,
+    pub(crate) sync_tx_sender: Sender<'b, ThreadModeRawMutex, (Frame, Instant), SYNC_TX_QUEUE_SIZE>,
+    pub(crate) sync_window_expired: &'b Signal<ThreadModeRawMutex, bool>,
+    pub(crate) nmt_state_changed: &'b Signal<ThreadModeRawMutex, NmtState>,
+}
+
+impl<'a, 'b, 'c, const N: usize, const R: usize, const T: usize> TpdoProducer<'a, 'b, 'c, N, R, T> {
+    /// Runs the producer. Without a running timer the configuration is re-read every `idle_timeout`,
+    /// so an event timer enabled over SDO starts within that time.
+    pub async fn run(&self, idle_timeout: Duration) -> ! {
+        let mut states = [TpdoState::new(); T];
+
+        loop {
+            let deadline = states
+                .iter()
+                .filter_map(TpdoState::deadline)
+                .min()
+                .unwrap_or(Instant::now() + idle_timeout);
+
+            let request = match select3(self.requests.receive(), Timer::at(deadline), self.nmt_state_changed.wait()).await {
+                Either3::First(request) => request,
+                Either3::Second(_) => {
+                    for (n, state) in states.iter_mut().enumerate() {
+                        self.process_timers(n as u16, state).await;
+                    }
+                    continue;
+                }
+                // Pending events, timers and SYNC counts are dropped when leaving operational state
+                Either3::Third(nmt_state) => {
+                    if !nmt_state.allows(NmtService::Pdo) {
+                        states = [TpdoState::new(); T];
+                    }
+                    continue;
+                }
+            };
+
+            match request {
+                TpdoRequest::Event(n) => match states.get_mut(n as usize) {
+                    Some(state) => self.process_event(n, state).await,
+                    None => warn!("TpdoProducer: TPDO {} not served", n + 1),
+                },
+                TpdoRequest::Sync { counter, window_end } => {
+                    for (n, state) in states.iter_mut().enumerate() {
+                        self.process_sync(n as u16, state, counter, window_end).await;
+                    }
+                }
+                TpdoRequest::Rtr(n) => match states.get_mut(n as usize) {
+                    Some(state) => self.process_rtr(n, state).await,
+                    None => warn!("TpdoProducer: TPDO {} not served", n + 1),
+                },
+            }
+        }
+    }
+
+    async fn process_event(&self, n: u16, state: &mut TpdoState) {
+        match self.transmission_type(n).await {
+            Some(TransmissionType::SynchronousAcyclic) => state.event_pending = true,
+            Some(TransmissionType::Event) => self.send_event(n, state).await,
+            _ => (),
+        }
+    }
+
+    async fn process_timers(&self, n: u16, state: &mut TpdoState) {
+        if self.transmission_type(n).await != Some(TransmissionType::Event) {
+            state.inhibited_event = false;
+            state.event_timer = None;
+            return;
+        }
+
+        let now = Instant::now();
+        if state.inhibited_event && now >= state.inhibited_until {
+            self.send_event(n, state).await;
+            return;
+        }
+
+        match (self.timing(n).await.event_timer, state.event_timer) {
+            (None, _) => state.event_timer = None,
+            (Some(period), None) => state.event_timer = Some(now + period),
+            (Some(_), Some(elapsed)) if elapsed <= now => self.send_event(n, state).await,
+            _ => (),
+        }
+    }
+
+    // Sends an event-driven TPDO, or delays it until its inhibit time has passed
+    async fn send_event(&self, n: u16, state: &mut TpdoState) {
+        let now = Instant::now();
+        if now < state.inhibited_until {
+            state.inhibited_event = true;
+            state.event_timer = None;
+            return;
+        }
+
+        let timing = self.timing(n).await;
+        state.inhibited_event = false;
+        state.inhibited_until = now + timing.inhibit_time;
+        state.event_timer = timing.event_timer.map(|period| now + period);
+
+        let frame = self.build_frame(n).await;
+        self.send(frame, None).await;
+    }
+
+    async fn process_sync(&self, n: u16, state: &mut TpdoState, counter: Option<u8>, window_end: Option<Instant>) {
+        match self.transmission_type(n).await {
+            Some(TransmissionType::SynchronousAcyclic) if state.event_pending => {
+                state.event_pending = false;
+                let frame = self.build_frame(n).await;
+                self.send(frame, window_end).await;
+            }
+            Some(TransmissionType::SynchronousCyclic(period)) => {
+                if !state.sync_started {
+                    // The first transmission happens with the SYNC whose counter equals the start value
+                    let start = self.sync_start_value(n).await;
+                    match counter {
+                        Some(counter) if start != 0 && counter != start => return,
+                        Some(_) if start != 0 => state.sync_count = period - 1,
+                        _ => (),
+                    }
+                    state.sync_started = true;
+                }
+
+                state.sync_count += 1;
+                if state.sync_count >= period {
+                    state.sync_count = 0;
+                    let frame = self.build_frame(n).await;
+                    self.send(frame, window_end).await;
+                }
+            }
+            Some(TransmissionType::SynchronousRtr) => state.sampled = self.build_frame(n).await,
+            _ => (),
+        }
+    }
+
+    async fn process_rtr(&self, n: u16, state: &mut TpdoState) {
+        // Synchronous RTR-only TPDOs answer with the data sampled at the last SYNC
+        let frame = match self.transmission_type(n).await {
+            Some(TransmissionType::SynchronousRtr) => state.sampled,
+            Some(_) => self.build_frame(n).await,
+            None => None,
+        };
+        self.send(frame, None).await;
+    }
+
+    async fn transmission_type(&self, n: u16) -> Option<TransmissionType> {
+        let locked_od = self.object_dictionary.lock().await;
+        let value = pdo::parameter(&locked_od, TPDO_COMMUNICATION + n, 2).ok()?;
+        TransmissionType::new(value as u8)
+    }
+
+    async fn sync_start_value(&self, n: u16) -> u8 {
+        let locked_od = self.object_dictionary.lock().await;
+        pdo::parameter(&locked_od, TPDO_COMMUNICATION + n, 6).unwrap_or(0) as u8
+    }
+
+    async fn timing(&self, n: u16) -> Timing {
+        let locked_od = self.object_dictionary.lock().await;
+        let inhibit_time = pdo::parameter(&locked_od, TPDO_COMMUNICATION + n, 3).unwrap_or(0);
+        let event_timer = pdo::parameter(&locked_od, TPDO_COMMUNICATION + n, 5).unwrap_or(0);
+
+        Timing {
+            // In multiples of 100 us
+            inhibit_time: Duration::from_micros(100 * inhibit_time as u64),
+            // In ms, 0 = disabled
+            event_timer: (event_timer != 0).then(|| Duration::from_millis(event_timer as u64)),
+        }
+    }
+
+    // Builds the frame of TPDO `n` from the current object values, `None` if the TPDO is not valid
+    async fn build_frame(&self, n: u16) -> Option<Frame> {
+        let locked_od = self.object_dictionary.lock().await;
+        let cob_id = PdoCobId(pdo::parameter(&locked_od, TPDO_COMMUNICATION + n, 1).ok()?);
+        if !cob_id.is_valid() || cob_id.is_extended() {
+            return None;
+        }
+
+        match pdo::pack(&locked_od, TPDO_MAPPING + n) {
+            Ok((data, len)) => Frame::new_standard(cob_id.can_id() as u16, &data[..len]).ok(),
+            Err(e) => {
+                warn!("TpdoProducer: TPDO {} not sent: {}", n + 1, e);
+                None
+            }
+        }
+    }
+
+    // PDOs are only sent in operational state, synchronous ones only within the synchronous window
+    async fn send(&self, frame: Option<Frame>, window_end: Option<Instant>) {
+        let Some(frame) = frame else {
+            return;
+        };
+
+        if !self.context.lock().await.nmt_state.allows(NmtService::Pdo) {
+            return;
+        }
+
+        match window_end {
+            Some(window_end) if Instant::now() > window_end => self.sync_window_expired.signal(true),
+            // The sender discards the frame if it cannot be sent in time
+            Some(window_end) => self.sync_tx_sender.send((frame, window_end)).await,
+            None => self.can_tx_sender.send(frame).await,
+        }
+    }
+}